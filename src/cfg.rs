@@ -1,4 +1,4 @@
-use config::{Config, Environment};
+use config::{Config, ConfigError, Environment};
 use lettre::message::Mailbox;
 use serde::{Deserialize, Serialize};
 
@@ -22,6 +22,10 @@ fn default_clt_addr() -> String {
     "http://127.0.0.1:8080".to_string()
 }
 
+fn default_srv_url() -> String {
+    "http://127.0.0.1:8000".to_string()
+}
+
 fn default_hash_cost() -> u8 {
     4
 }
@@ -38,6 +42,58 @@ fn default_smtp_username() -> String {
     "root".to_string()
 }
 
+fn default_verification_ttl() -> i64 {
+    24 * 60 * 60
+}
+
+fn default_reset_ttl() -> i64 {
+    60 * 60
+}
+
+fn default_login_failure_limit() -> i32 {
+    5
+}
+
+fn default_ip_failure_limit() -> i32 {
+    20
+}
+
+fn default_lockout_max() -> i64 {
+    60 * 60
+}
+
+fn default_totp_issuer() -> String {
+    "prepublish".to_string()
+}
+
+fn default_oidc_client_id() -> String {
+    "prepublish".to_string()
+}
+
+fn default_oidc_redirect_url() -> String {
+    "http://127.0.0.1:8000/oidc/callback".to_string()
+}
+
+fn default_avatar_max_size() -> usize {
+    1024 * 1024
+}
+
+fn default_oai_base_url() -> url::Url {
+    "http://127.0.0.1:8000/oai".parse().unwrap()
+}
+
+fn default_oai_repository_name() -> String {
+    "prepublish".to_string()
+}
+
+fn default_forwarded_header() -> String {
+    "x-forwarded-for".to_string()
+}
+
+fn default_session_ttl() -> u64 {
+    7 * 24 * 60 * 60
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Eq, PartialEq)]
 #[derive(Clone)]
@@ -53,6 +109,9 @@ pub struct AppConfig {
     pub(crate) srv_addr: String,
     #[serde(default = "default_clt_addr")]
     pub(crate) clt_addr: String,
+    /// Where clients reach this server, for links to it that leave the client such as in feeds.
+    #[serde(default = "default_srv_url")]
+    pub(crate) srv_url: String,
     #[serde(default = "default_hash_cost")]
     pub(crate) hash_cost: u8,
     #[serde(default = "default_mail_box")]
@@ -63,14 +122,73 @@ pub struct AppConfig {
     pub(crate) smtp_username: String,
     #[serde(default)]
     pub(crate) smtp_password: String,
+    #[serde(default = "default_verification_ttl")]
+    pub(crate) verification_ttl: i64,
+    #[serde(default = "default_reset_ttl")]
+    pub(crate) reset_ttl: i64,
+    /// At least `SESSION_SECRET_MIN` bytes; a random one is used when unset, which logs everyone out on restart.
+    #[serde(default)]
+    pub(crate) session_secret: Option<String>,
+    #[serde(default = "default_session_ttl")]
+    pub(crate) session_ttl: u64,
+    #[serde(default = "default_login_failure_limit")]
+    pub(crate) login_failure_limit: i32,
+    #[serde(default = "default_ip_failure_limit")]
+    pub(crate) ip_failure_limit: i32,
+    #[serde(default = "default_lockout_max")]
+    pub(crate) lockout_max: i64,
+    /// Comma-separated addresses of reverse proxies whose forwarded header is believed.
+    #[serde(default)]
+    pub(crate) trusted_proxies: String,
+    /// Where trusted proxies put the client address, appending to what they received.
+    #[serde(default = "default_forwarded_header")]
+    pub(crate) forwarded_header: String,
+    #[serde(default = "default_totp_issuer")]
+    pub(crate) totp_issuer: String,
+    /// Withholds administrator and editor powers from accounts without TOTP.
+    #[serde(default)]
+    pub(crate) totp_required: bool,
+    /// Single sign-on is offered only when an issuer is set.
+    #[serde(default)]
+    pub(crate) oidc_issuer: Option<String>,
+    #[serde(default = "default_oidc_client_id")]
+    pub(crate) oidc_client_id: String,
+    #[serde(default)]
+    pub(crate) oidc_client_secret: Option<String>,
+    /// Where the issuer sends users back to, i.e. `/oidc/callback` on this server.
+    #[serde(default = "default_oidc_redirect_url")]
+    pub(crate) oidc_redirect_url: String,
+    /// In bytes, before resizing; request bodies are capped at 2 MiB regardless.
+    #[serde(default = "default_avatar_max_size")]
+    pub(crate) avatar_max_size: usize,
+    /// The Crossref member named in DOI deposits; `sender` is used when unset.
+    #[serde(default)]
+    pub(crate) crossref_depositor: Option<Mailbox>,
+    /// Where harvesters reach `/oai`; its host also names the repository in OAI identifiers.
+    #[serde(default = "default_oai_base_url")]
+    pub(crate) oai_base_url: url::Url,
+    #[serde(default = "default_oai_repository_name")]
+    pub(crate) oai_repository_name: String,
 }
 
+/// What the session layer requires of its secret.
+const SESSION_SECRET_MIN: usize = 64;
+
 impl AppConfig {
-    pub(crate) fn new() -> Self {
-        let config = Config::builder()
+    pub(crate) fn new() -> Result<Self, ConfigError> {
+        let res: Self = Config::builder()
             .add_source(Environment::with_prefix("PREPUBLISH"))
-            .build()
-            .unwrap();
-        config.try_deserialize().unwrap()
+            .build()?
+            .try_deserialize()?;
+        if let Some(session_secret) = &res.session_secret {
+            if session_secret.len() < SESSION_SECRET_MIN {
+                return Err(ConfigError::Message(format!(
+                    "PREPUBLISH_SESSION_SECRET has {} bytes, but needs at least {}!",
+                    session_secret.len(),
+                    SESSION_SECRET_MIN
+                )));
+            }
+        }
+        Ok(res)
     }
 }
@@ -1,24 +1,96 @@
 use std::{net::SocketAddr, str::FromStr, sync::Arc};
 
 use axum::http::{header, HeaderValue, Method};
+use rand::Rng;
+use sea_orm_migration::MigratorTrait;
 
 mod cfg;
 mod mongo_entities;
+mod mongo_migrations;
 mod routes;
 mod sql_entities;
+mod sql_migrations;
 mod state;
 
+/// How many events a slow live stream may fall behind before it is ended.
+const EVENT_CAPACITY: usize = 1024;
+
 #[tokio::main]
 async fn main() {
+    tracing_subscriber::fmt::init();
     let config = tokio::task::spawn_blocking(cfg::AppConfig::new)
         .await
-        .unwrap();
+        .unwrap()
+        .unwrap_or_else(|err| panic!("Invalid configuration: {}", err));
     let sql_db = sea_orm::Database::connect(config.sql_db_url).await.unwrap();
+    sql_migrations::Migrator::up(&sql_db, None).await.unwrap();
+    sql_migrations::backfill_hash_costs(&sql_db, config.hash_cost)
+        .await
+        .unwrap();
     let mongo_db = mongodm::prelude::MongoClient::with_uri_str(config.mongo_srv_url)
         .await
         .unwrap()
         .database(&config.mongo_db_nm);
+    mongo_migrations::run(&mongo_db).await.unwrap();
+    mongodm::sync_indexes::<mongo_entities::thesis::Thesis>(&mongo_db)
+        .await
+        .unwrap();
+    mongodm::sync_indexes::<mongo_entities::session::StoredSession>(&mongo_db)
+        .await
+        .unwrap();
+    mongodm::sync_indexes::<mongo_entities::invitation::Invitation>(&mongo_db)
+        .await
+        .unwrap();
+    mongodm::sync_indexes::<mongo_entities::audit::AuditLog>(&mongo_db)
+        .await
+        .unwrap();
+    mongodm::sync_indexes::<mongo_entities::notification::Notification>(&mongo_db)
+        .await
+        .unwrap();
+    let session_secret = config
+        .session_secret
+        .map(String::into_bytes)
+        .unwrap_or_else(|| rand::thread_rng().gen::<[u8; 128]>().to_vec());
+    let clt_addr = Arc::new(config.clt_addr.clone());
+    let srv_url = Arc::new(config.srv_url.trim_end_matches('/').to_string());
     let hash_cost = config.hash_cost;
+    let verification_ttl = chrono::Duration::seconds(config.verification_ttl);
+    let reset_ttl = chrono::Duration::seconds(config.reset_ttl);
+    let login_failure_limit = config.login_failure_limit;
+    let ip_failure_limit = config.ip_failure_limit;
+    let lockout_max = chrono::Duration::seconds(config.lockout_max);
+    let trusted_proxies = Arc::new(
+        config
+            .trusted_proxies
+            .split(',')
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| proxy.parse().unwrap())
+            .collect(),
+    );
+    let forwarded_header = header::HeaderName::from_str(&config.forwarded_header).unwrap();
+    let totp_issuer = Arc::new(config.totp_issuer);
+    let totp_required = config.totp_required;
+    let oidc_issuer = config.oidc_issuer.map(Arc::new);
+    let oidc_client_id = Arc::new(config.oidc_client_id);
+    let oidc_client_secret = config.oidc_client_secret.map(Arc::new);
+    let oidc_redirect_url = Arc::new(config.oidc_redirect_url);
+    let avatar_max_size = config.avatar_max_size;
+    let crossref_depositor = Arc::new(
+        config
+            .crossref_depositor
+            .unwrap_or_else(|| config.sender.clone()),
+    );
+    let oai_base_url = Arc::new(config.oai_base_url);
+    let oai_repository_name = Arc::new(config.oai_repository_name);
+    let (events, _) = tokio::sync::broadcast::channel(EVENT_CAPACITY);
+    // A standalone server has no change streams; everything but live notifications still works.
+    if let Err(err) = routes::common::notify::relay(mongo_db.clone(), events.clone()).await {
+        tracing::warn!(
+            "Live notifications are off, as MongoDB cannot be watched: {}",
+            err
+        );
+    }
     let sender = Arc::new(config.sender);
     let smtp = <lettre::AsyncSmtpTransport<lettre::Tokio1Executor>>::relay(&config.relay)
         .unwrap()
@@ -29,39 +101,63 @@ async fn main() {
         ))
         .build::<lettre::Tokio1Executor>();
     //assert!(smtp.test_connection().await.unwrap());
-    let app = routes::new()
-        .layer(
-            tower_http::cors::CorsLayer::new()
-                .allow_origin(config.clt_addr.parse::<HeaderValue>().unwrap())
-                .allow_headers([
-                    header::ACCEPT,
-                    header::CONTENT_TYPE,
-                    header::CONTENT_DISPOSITION,
-                    header::CONTENT_ENCODING,
-                    header::CONTENT_LENGTH,
-                    header::COOKIE,
-                    header::SET_COOKIE,
-                    header::HeaderName::from_str("x-csrf-token").unwrap(),
-                ])
-                .allow_methods([
-                    Method::HEAD,
-                    Method::GET,
-                    Method::POST,
-                    Method::PATCH,
-                    Method::DELETE,
-                ])
-                .allow_credentials(true)
-                .expose_headers(["x-csrf-token".parse().unwrap()]),
-        )
-        .with_state(state::AppState {
-            sql_db,
-            mongo_db,
-            hash_cost,
-            sender,
-            smtp,
-        });
+    let app = routes::new(
+        routes::common::session::MongoSessionStore::new(mongo_db.clone()),
+        &session_secret,
+        std::time::Duration::from_secs(config.session_ttl),
+    )
+    .layer(
+        tower_http::cors::CorsLayer::new()
+            .allow_origin(config.clt_addr.parse::<HeaderValue>().unwrap())
+            .allow_headers([
+                header::ACCEPT,
+                header::CONTENT_TYPE,
+                header::CONTENT_DISPOSITION,
+                header::CONTENT_ENCODING,
+                header::CONTENT_LENGTH,
+                header::COOKIE,
+                header::SET_COOKIE,
+                header::HeaderName::from_str("x-csrf-token").unwrap(),
+            ])
+            .allow_methods([
+                Method::HEAD,
+                Method::GET,
+                Method::POST,
+                Method::PATCH,
+                Method::DELETE,
+            ])
+            .allow_credentials(true)
+            .expose_headers(["x-csrf-token".parse().unwrap()]),
+    )
+    .with_state(state::AppState {
+        sql_db,
+        mongo_db,
+        clt_addr,
+        srv_url,
+        hash_cost,
+        verification_ttl,
+        reset_ttl,
+        login_failure_limit,
+        ip_failure_limit,
+        lockout_max,
+        trusted_proxies,
+        forwarded_header,
+        totp_issuer,
+        totp_required,
+        oidc_issuer,
+        oidc_client_id,
+        oidc_client_secret,
+        oidc_redirect_url,
+        avatar_max_size,
+        crossref_depositor,
+        oai_base_url,
+        oai_repository_name,
+        events,
+        sender,
+        smtp,
+    });
     axum::Server::bind(&SocketAddr::from_str(&config.srv_addr).unwrap())
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
@@ -0,0 +1,58 @@
+use mongodm::bson::Bson;
+use mongodm::prelude::ObjectId;
+use mongodm::{field, CollectionConfig, Index, Indexes, Model};
+use serde::{Deserialize, Serialize};
+
+#[derive(utoipa::ToSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AuditAction {
+    MagazineCreated,
+    MagazineReplaced,
+    ThesisReplaced,
+    ThesisWithdrawn,
+    VersionEdited,
+    VersionAdjudged,
+    RoleGranted,
+    RoleRevoked,
+    AccountEnabled,
+    AccountDisabled,
+    PasswordResetForced,
+}
+
+/// One privileged change, never updated nor deleted once written.
+#[derive(utoipa::ToSchema)]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct AuditLog {
+    #[serde(default)]
+    pub(crate) _id: ObjectId,
+    pub(crate) actor_id: ObjectId,
+    pub(crate) action: AuditAction,
+    /// An `ObjectId`, an email address or a role grant id, depending on `action`.
+    pub(crate) target_id: String,
+    #[serde(default)]
+    pub(crate) before: Option<Bson>,
+    #[serde(default)]
+    pub(crate) after: Option<Bson>,
+    pub(crate) at: chrono::DateTime<chrono::Utc>,
+}
+
+impl CollectionConfig for AuditLog {
+    fn collection_name() -> &'static str {
+        "audit_logs"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new()
+            .with(Index::new(field!(actor_id in AuditLog)).with_key(field!(at in AuditLog)))
+            .with(Index::new(field!(target_id in AuditLog)).with_key(field!(at in AuditLog)))
+            .with(Index::new(field!(action in AuditLog)).with_key(field!(at in AuditLog)))
+    }
+}
+
+impl Model for AuditLog {
+    type CollConf = Self;
+}
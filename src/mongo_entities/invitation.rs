@@ -0,0 +1,37 @@
+use mongodm::prelude::ObjectId;
+use mongodm::{field, CollectionConfig, Index, IndexOption, Indexes, Model};
+use serde::{Deserialize, Serialize};
+
+/// A co-author of a thesis known only by email until they have an account.
+#[derive(utoipa::ToSchema)]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Invitation {
+    #[serde(default)]
+    pub(crate) _id: ObjectId,
+    pub(crate) thesis_id: ObjectId,
+    pub(crate) email: lettre::Address,
+    /// Index among the authors and the pending invitees of the thesis taken together.
+    pub(crate) position: u32,
+    pub(crate) inviter_id: ObjectId,
+    pub(crate) invited_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl CollectionConfig for Invitation {
+    fn collection_name() -> &'static str {
+        "invitations"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new()
+            .with(
+                Index::new(field!(thesis_id in Invitation))
+                    .with_key(field!(email in Invitation))
+                    .with_option(IndexOption::Unique),
+            )
+            .with(Index::new(field!(email in Invitation)))
+    }
+}
+
+impl Model for Invitation {
+    type CollConf = Self;
+}
@@ -1,9 +1,16 @@
+use mongodm::bson::Bson;
 use mongodm::prelude::ObjectId;
+use serde::{de, Deserialize, Deserializer};
 use utoipa::openapi::{RefOr, Schema};
 
+pub(crate) mod audit;
+pub(crate) mod invitation;
+pub(crate) mod notification;
 pub(crate) mod paper_collection;
 pub(crate) mod profile;
+pub(crate) mod session;
 pub(crate) mod thesis;
+pub(crate) mod throttle;
 
 pub(crate) struct ObjectIdDef;
 
@@ -32,3 +39,22 @@ impl<'__s> utoipa::ToSchema<'__s> for ObjectIdDef {
         )
     }
 }
+
+/// Reads times stored as BSON dates as well as the RFC 3339 strings they are written as in JSON.
+/// Serializing still gives those strings, so such fields must be written with `$set` and
+/// `DateTime::from_chrono` rather than along with their whole document.
+pub(crate) fn optional_datetime<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>, D::Error> {
+    match Option::<Bson>::deserialize(deserializer)? {
+        None | Some(Bson::Null) => Ok(None),
+        Some(Bson::DateTime(date_time)) => Ok(Some(date_time.to_chrono())),
+        Some(Bson::String(date_time)) => chrono::DateTime::parse_from_rfc3339(&date_time)
+            .map(|date_time| Some(date_time.into()))
+            .map_err(de::Error::custom),
+        Some(other) => Err(de::Error::custom(format!(
+            "Expected a date, got {}!",
+            other
+        ))),
+    }
+}
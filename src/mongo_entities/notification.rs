@@ -0,0 +1,58 @@
+use mongodm::prelude::ObjectId;
+use mongodm::{field, CollectionConfig, Index, Indexes, Model};
+use serde::{Deserialize, Serialize};
+
+#[derive(utoipa::ToSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NotificationKind {
+    ReviewerAssigned,
+    ReviewSubmitted,
+    VersionPassed,
+    VersionRejected,
+    CommentPosted,
+    ReplyPosted,
+}
+
+#[derive(utoipa::ToSchema)]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Notification {
+    #[serde(default)]
+    pub(crate) _id: ObjectId,
+    pub(crate) recipient_id: ObjectId,
+    pub(crate) kind: NotificationKind,
+    /// The version, review or comment the notification is about.
+    pub(crate) target_id: ObjectId,
+    pub(crate) message: String,
+    pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub(crate) read_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Whether the recipient wanted it streamed live at the time.
+    #[serde(default)]
+    pub(crate) push: bool,
+}
+
+impl CollectionConfig for Notification {
+    fn collection_name() -> &'static str {
+        "notifications"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new()
+            .with(
+                Index::new(field!(recipient_id in Notification))
+                    .with_key(field!(created_at in Notification)),
+            )
+            .with(
+                Index::new(field!(recipient_id in Notification))
+                    .with_key(field!(read_at in Notification)),
+            )
+    }
+}
+
+impl Model for Notification {
+    type CollConf = Self;
+}
@@ -0,0 +1,29 @@
+use axum_sessions::async_session::Session;
+use mongodm::bson::DateTime;
+use mongodm::{field, CollectionConfig, Index, IndexOption, Indexes, Model};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StoredSession {
+    pub(crate) _id: String,
+    pub(crate) session: Session,
+    #[serde(default)]
+    pub(crate) expires_at: Option<DateTime>,
+}
+
+impl CollectionConfig for StoredSession {
+    fn collection_name() -> &'static str {
+        "sessions"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new().with(
+            Index::new(field!(expires_at in StoredSession))
+                .with_option(IndexOption::ExpireAfterSeconds(0)),
+        )
+    }
+}
+
+impl Model for StoredSession {
+    type CollConf = Self;
+}
@@ -1,7 +1,7 @@
 use std::collections::BTreeSet;
 
 use async_recursion::async_recursion;
-use mongodm::bson::to_bson;
+use mongodm::bson::{to_bson, DateTime};
 use mongodm::prelude::{
     MongoCursor, MongoDatabase, MongoDeleteResult, MongoError, MongoFindOneAndUpdateOptions,
     MongoReturnDocument, ObjectId,
@@ -11,6 +11,8 @@ use mongodm::{
 };
 use serde::{Deserialize, Serialize};
 
+use crate::mongo_entities::invitation::Invitation;
+
 #[derive(utoipa::ToSchema)]
 #[derive(Serialize, Deserialize)]
 pub(crate) struct ThesisId {
@@ -22,6 +24,12 @@ pub(crate) struct ThesisId {
     pub(crate) is_passed: bool,
     #[serde(default)]
     pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+    /// When a version of the thesis last passed, stored as a BSON date.
+    #[serde(default, deserialize_with = "super::optional_datetime")]
+    pub(crate) passed_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// When the thesis or its list of authors was last edited, stored as a BSON date.
+    #[serde(default, deserialize_with = "super::optional_datetime")]
+    pub(crate) modified_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(utoipa::ToSchema)]
@@ -30,13 +38,84 @@ pub(crate) struct Thesis {
     #[serde(flatten)]
     pub(crate) id: ThesisId,
     pub(crate) author_ids: Vec<ObjectId>,
-    //pub(crate) magazine_id: ObjectId,
+    /// Required of new theses; older ones may lack it, and then only global grants cover them.
+    #[serde(default)]
+    pub(crate) magazine_id: Option<ObjectId>,
     #[serde(default)]
     pub(crate) doi: Option<String>,
     pub(crate) title: String,
     pub(crate) abstraction: String,
     pub(crate) keywords: Vec<String>,
     pub(crate) languages: BTreeSet<String>,
+    /// Stemming language of the text index, derived from `languages` on every write.
+    #[serde(default = "no_stemming")]
+    pub(crate) text_language: String,
+}
+
+/// Languages with MongoDB text search support, by their ISO 639-1 codes.
+const STEMMED_LANGUAGES: [&str; 15] = [
+    "da", "de", "en", "es", "fi", "fr", "hu", "it", "nb", "nl", "pt", "ro", "ru", "sv", "tr",
+];
+
+fn no_stemming() -> String {
+    "none".to_string()
+}
+
+impl Thesis {
+    /// Theses that passed before `passed_at` was recorded have it backfilled from their creation,
+    /// which also stands in for theses that have not passed.
+    pub(crate) fn last_passed_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.id.passed_at.unwrap_or(self.id.created_at)
+    }
+
+    /// The later of passing and the last edit.
+    pub(crate) fn last_modified_at(&self) -> chrono::DateTime<chrono::Utc> {
+        let passed_at = self.last_passed_at();
+        self.id
+            .modified_at
+            .map_or(passed_at, |modified_at| modified_at.max(passed_at))
+    }
+
+    pub(crate) fn stemming_language(&self) -> String {
+        Self::stemming_language_of(&self.languages)
+    }
+
+    /// The first of `languages` that MongoDB can stem, so that e.g. `en-GB` is stemmed as `en`.
+    pub(crate) fn stemming_language_of<'a>(
+        languages: impl IntoIterator<Item = &'a String>,
+    ) -> String {
+        languages
+            .into_iter()
+            .map(|language| {
+                language
+                    .split(['-', '_'])
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase()
+            })
+            .find(|language| STEMMED_LANGUAGES.contains(&language.as_str()))
+            .unwrap_or_else(no_stemming)
+    }
+}
+
+/// Weighted towards titles; each thesis is stemmed in its `text_language`.
+fn text_index() -> Index {
+    let mut res = Index::new_with_text(field!(title in Thesis));
+    res.add_key_with_text(field!(abstraction in Thesis));
+    res.add_key_with_text(field!(keywords in Thesis));
+    res.with_option(IndexOption::Weights(vec![
+        (field!(title in Thesis).to_string(), 10),
+        (field!(keywords in Thesis).to_string(), 5),
+        (field!(abstraction in Thesis).to_string(), 1),
+    ]))
+    .with_option(IndexOption::Custom {
+        name: "default_language".to_string(),
+        value: no_stemming().into(),
+    })
+    .with_option(IndexOption::Custom {
+        name: "language_override".to_string(),
+        value: field!(text_language in Thesis).into(),
+    })
 }
 
 impl CollectionConfig for Thesis {
@@ -52,12 +131,22 @@ impl CollectionConfig for Thesis {
                     .with_option(IndexOption::Unique),
             )
             .with(Index::new(field!(is_passed in ThesisId)))
+            .with(Index::new(field!(passed_at in ThesisId)))
             .with(Index::new(field!(author_ids in Thesis)))
-            //.with(Index::new(field!(magazine_id in Thesis)))
-            .with(Index::new(field!(doi in Thesis)))
+            .with(Index::new(field!(magazine_id in Thesis)))
+            .with(
+                Index::new(field!(doi in Thesis))
+                    .with_option(IndexOption::Unique)
+                    .with_option(IndexOption::PartialFilterExpression(doc! {
+                        field!(doi in Thesis): {
+                            "$type": "string"
+                        }
+                    })),
+            )
             .with(Index::new(field!(title in Thesis)))
             .with(Index::new(field!(keywords in Thesis)))
             .with(Index::new(field!(languages in Thesis)))
+            .with(text_index())
     }
 }
 
@@ -111,6 +200,9 @@ pub(crate) struct Version {
     pub(crate) state: VersionState,
     pub(crate) review_state: ReviewState,
     pub(crate) downloads: i32,
+    /// Only set on versions that have passed since this was recorded, stored as a BSON date.
+    #[serde(default, deserialize_with = "super::optional_datetime")]
+    pub(crate) passed_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl CollectionConfig for Version {
@@ -282,7 +374,8 @@ impl Version {
                     Set: {
                         field!(state in Version): to_bson(&VersionState::Passed(true))?,
                         field!(major_num in Version): self.major_num + 1,
-                        field!(minor_num in Version): 0
+                        field!(minor_num in Version): 0,
+                        field!(passed_at in Version): DateTime::now()
                     }
                 },
                 MongoFindOneAndUpdateOptions::builder()
@@ -311,7 +404,8 @@ impl Version {
                 },
                 doc! {
                     Set: {
-                        field!(is_passed in ThesisId): true
+                        field!(is_passed in ThesisId): true,
+                        field!(passed_at in ThesisId): DateTime::now()
                     }
                 },
                 None,
@@ -369,6 +463,14 @@ impl Thesis {
             let version = versions.deserialize_current()?;
             version.withdraw(db.clone()).await?;
         }
+        db.repository::<Invitation>()
+            .delete_many(
+                doc! {
+                    field!(thesis_id in Invitation): self.id._id
+                },
+                None,
+            )
+            .await?;
         db.repository::<Self>()
             .delete_many(
                 doc! {
@@ -0,0 +1,53 @@
+use mongodm::bson::DateTime;
+use mongodm::prelude::ObjectId;
+use mongodm::{field, CollectionConfig, Index, Indexes, Model};
+use serde::{Deserialize, Serialize};
+
+/// Recent authentication failures of one account or one client address.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Throttle {
+    pub(crate) _id: String,
+    pub(crate) failures: i32,
+    pub(crate) last_failed_at: DateTime,
+    #[serde(default)]
+    pub(crate) locked_until: Option<DateTime>,
+}
+
+impl CollectionConfig for Throttle {
+    fn collection_name() -> &'static str {
+        "throttles"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new()
+    }
+}
+
+impl Model for Throttle {
+    type CollConf = Self;
+}
+
+#[derive(utoipa::ToSchema)]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Lockout {
+    #[serde(default)]
+    pub(crate) _id: ObjectId,
+    pub(crate) subject: String,
+    pub(crate) failures: i32,
+    pub(crate) locked_at: chrono::DateTime<chrono::Utc>,
+    pub(crate) locked_until: chrono::DateTime<chrono::Utc>,
+}
+
+impl CollectionConfig for Lockout {
+    fn collection_name() -> &'static str {
+        "lockouts"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new().with(Index::new(field!(subject in Lockout)))
+    }
+}
+
+impl Model for Lockout {
+    type CollConf = Self;
+}
@@ -0,0 +1,254 @@
+//! Brings documents written by earlier versions up to date before the indexes are synced.
+//! Every step is idempotent, so all of them simply run on each start.
+
+use std::collections::BTreeMap;
+
+use futures_util::TryStreamExt;
+use mongodm::bson::{DateTime, Document};
+use mongodm::prelude::{MongoDatabase, MongoFindOptions};
+use mongodm::{doc, field, CollectionConfig, ToRepository};
+
+use crate::mongo_entities::thesis::{Thesis, ThesisId, Version};
+use crate::mongo_entities::throttle::Throttle;
+use crate::routes::thesis::normalise_doi;
+
+/// Where DOIs that duplicate another thesis's end up, along with that thesis.
+const DROPPED_DOIS: &str = "dropped_dois";
+
+pub(crate) async fn run(db: &MongoDatabase) -> anyhow::Result<()> {
+    drop_stale_throttles(db).await?;
+    derive_text_languages(db).await?;
+    normalise_dois(db).await?;
+    date_passed_at(db, Thesis::collection_name()).await?;
+    date_passed_at(db, Version::collection_name()).await?;
+    backfill_passed_at(db).await?;
+    Ok(())
+}
+
+/// Theses stored before text search existed get the language the text index stems them in.
+async fn derive_text_languages(db: &MongoDatabase) -> anyhow::Result<()> {
+    let theses = db.collection::<Document>(Thesis::collection_name());
+    let mut cursor = theses
+        .find(
+            doc! {
+                field!(text_language in Thesis): {
+                    "$exists": false
+                }
+            },
+            MongoFindOptions::builder()
+                .projection(doc! {
+                    field!(languages in Thesis): 1
+                })
+                .build(),
+        )
+        .await?;
+    while let Some(thesis) = cursor.try_next().await? {
+        let languages: Vec<String> = thesis
+            .get_array(field!(languages in Thesis))
+            .map(|languages| {
+                languages
+                    .iter()
+                    .filter_map(|language| language.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        theses
+            .update_one(
+                doc! {
+                    "_id": thesis.get("_id")
+                },
+                doc! {
+                    "$set": {
+                        field!(text_language in Thesis): Thesis::stemming_language_of(&languages)
+                    }
+                },
+                None,
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Throttles used to keep their times as strings; they are short-lived, so those are just forgotten.
+async fn drop_stale_throttles(db: &MongoDatabase) -> anyhow::Result<()> {
+    db.repository::<Throttle>()
+        .delete_many(
+            doc! {
+                field!(last_failed_at in Throttle): {
+                    "$type": "string"
+                }
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// DOIs used to be kept as entered; now they are normalised, and where that makes theses share
+/// one, only the oldest keeps it so that the unique index can be built. The others are logged
+/// and copied to `DROPPED_DOIS`.
+async fn normalise_dois(db: &MongoDatabase) -> anyhow::Result<()> {
+    let theses = db.collection::<Document>(Thesis::collection_name());
+    let mut cursor = theses
+        .find(
+            doc! {
+                field!(doi in Thesis): {
+                    "$type": "string"
+                }
+            },
+            MongoFindOptions::builder()
+                .projection(doc! {
+                    field!(doi in Thesis): 1
+                })
+                .sort(doc! {
+                    "_id": 1
+                })
+                .build(),
+        )
+        .await?;
+    let mut seen = BTreeMap::new();
+    let mut normalised = Vec::new();
+    let mut duplicates = Vec::new();
+    let mut dropped = Vec::new();
+    while let Some(thesis) = cursor.try_next().await? {
+        let id = thesis.get_object_id("_id")?;
+        let doi = thesis.get_str(field!(doi in Thesis))?;
+        // Invalid ones stay, lower-cased, for an editor to correct.
+        let res = normalise_doi(doi).unwrap_or_else(|_| doi.trim().to_lowercase());
+        if let Some(kept_by) = seen.get(&res) {
+            tracing::warn!(
+                "Dropping DOI {} of thesis {}, as thesis {} keeps it",
+                doi,
+                id,
+                kept_by
+            );
+            duplicates.push(id);
+            dropped.push(doc! {
+                "thesis_id": id,
+                "doi": doi,
+                "kept_by": kept_by,
+                "dropped_at": DateTime::now()
+            });
+        } else {
+            seen.insert(res.clone(), id);
+            if res != doi {
+                normalised.push((id, res));
+            }
+        }
+    }
+    // Kept for an editor to reconcile.
+    if !dropped.is_empty() {
+        db.collection::<Document>(DROPPED_DOIS)
+            .insert_many(dropped, None)
+            .await?;
+    }
+    // Duplicates make way first, as they may hold the very spelling another thesis is given.
+    theses
+        .update_many(
+            doc! {
+                "_id": {
+                    "$in": duplicates
+                }
+            },
+            doc! {
+                "$unset": {
+                    field!(doi in Thesis): ""
+                }
+            },
+            None,
+        )
+        .await?;
+    for (id, doi) in normalised {
+        theses
+            .update_one(
+                doc! {
+                    "_id": id
+                },
+                doc! {
+                    "$set": {
+                        field!(doi in Thesis): doi
+                    }
+                },
+                None,
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// `passed_at` used to be stored as a string, which does not order like the time it stands for.
+async fn date_passed_at(db: &MongoDatabase, collection_name: &str) -> anyhow::Result<()> {
+    let collection = db.collection::<Document>(collection_name);
+    let mut cursor = collection
+        .find(
+            doc! {
+                field!(passed_at in ThesisId): {
+                    "$type": "string"
+                }
+            },
+            MongoFindOptions::builder()
+                .projection(doc! {
+                    field!(passed_at in ThesisId): 1
+                })
+                .build(),
+        )
+        .await?;
+    while let Some(document) = cursor.try_next().await? {
+        let passed_at =
+            chrono::DateTime::parse_from_rfc3339(document.get_str(field!(passed_at in ThesisId))?)?;
+        collection
+            .update_one(
+                doc! {
+                    "_id": document.get("_id")
+                },
+                doc! {
+                    "$set": {
+                        field!(passed_at in ThesisId): DateTime::from_chrono(passed_at)
+                    }
+                },
+                None,
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Theses that passed before `passed_at` was recorded get their creation, which is what their
+/// datestamps showed, so that filtering and sorting on `passed_at` agree with those.
+async fn backfill_passed_at(db: &MongoDatabase) -> anyhow::Result<()> {
+    let theses = db.collection::<Document>(Thesis::collection_name());
+    let mut cursor = theses
+        .find(
+            doc! {
+                field!(is_passed in ThesisId): true,
+                field!(passed_at in ThesisId): null
+            },
+            MongoFindOptions::builder()
+                .projection(doc! {
+                    field!(created_at in ThesisId): 1
+                })
+                .build(),
+        )
+        .await?;
+    while let Some(thesis) = cursor.try_next().await? {
+        let created_at = match thesis.get_str(field!(created_at in ThesisId)) {
+            Ok(created_at) => chrono::DateTime::parse_from_rfc3339(created_at)?.into(),
+            // As `ThesisId` reads a missing one.
+            Err(_) => chrono::DateTime::<chrono::Utc>::default(),
+        };
+        theses
+            .update_one(
+                doc! {
+                    "_id": thesis.get("_id")
+                },
+                doc! {
+                    "$set": {
+                        field!(passed_at in ThesisId): DateTime::from_chrono(created_at)
+                    }
+                },
+                None,
+            )
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,263 @@
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::{debug_handler, routing, Json, Router};
+use futures_util::TryStreamExt;
+use mongodm::bson::Document;
+use mongodm::prelude::{MongoFindOptions, ObjectId};
+use mongodm::{doc, field, ToRepository};
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+
+use crate::mongo_entities::audit::AuditAction;
+use crate::mongo_entities::profile::{Profile, ProfileId, PublicProfile};
+use crate::routes::common::audit;
+use crate::routes::common::auth::{AuthInfo, Permission, Scope};
+use crate::routes::common::err::AppError;
+use crate::routes::common::query::AppQuery;
+use crate::routes::common::secret;
+use crate::sql_entities::{
+    account::{self, ActiveModel},
+    prelude::{Account, RoleGrant},
+    role_grant,
+    sea_orm_active_enums::Role,
+};
+use crate::state::AppState;
+
+use super::role::{self, GrantInfo};
+use super::{api_token, find_own_email, password, set_password, try_find_account};
+
+/// An account as administrators see it, together with its profile and roles.
+#[derive(Serialize)]
+struct AccountInfo {
+    id: ObjectId,
+    email: String,
+    name: String,
+    is_verified: bool,
+    is_active: bool,
+    totp_enabled: bool,
+    roles: Vec<GrantInfo>,
+    created_at: chrono::NaiveDateTime,
+}
+
+/// Administration is refused to API tokens whatever their scopes.
+async fn find_administrator(
+    auth_info: &AuthInfo,
+    state: &AppState,
+) -> Result<lettre::Address, AppError> {
+    let email = find_own_email(auth_info, state).await?;
+    if !auth_info.permitted(Permission::Managing, Scope::Global) {
+        return Err(AppError::Forbidden(
+            "You are not an administrator!".to_string(),
+        ));
+    }
+    Ok(email)
+}
+
+async fn find_account(
+    state: &AppState,
+    email: &lettre::Address,
+) -> Result<account::Model, AppError> {
+    try_find_account(state, email)
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "Account with email {} does not exist!",
+            email
+        )))
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    /// Matched case-insensitively against names and email addresses.
+    #[serde(default)]
+    search: Option<String>,
+}
+
+#[debug_handler]
+async fn gets(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Query(query): Query<AppQuery>,
+    Query(search): Query<SearchQuery>,
+) -> Result<([(HeaderName, HeaderValue); 4], Json<Vec<AccountInfo>>), AppError> {
+    find_administrator(&auth_info, &state).await?;
+    let filter = match search.search {
+        Some(search) => {
+            let pattern = regex::escape(&search);
+            doc! {
+                "$or": [
+                    { field!(name in PublicProfile): { "$regex": &pattern, "$options": "i" } },
+                    { field!(email in ProfileId): { "$regex": &pattern, "$options": "i" } }
+                ]
+            }
+        }
+        None => Document::new(),
+    };
+    let count = state
+        .mongo_db
+        .repository::<Profile>()
+        .count_documents(filter.clone(), None)
+        .await?;
+    let profiles: Vec<Profile> = state
+        .mongo_db
+        .repository::<Profile>()
+        .find(
+            filter,
+            MongoFindOptions::builder()
+                .sort(doc! {
+                    field!(joining_at in ProfileId): 1
+                })
+                .skip(query.offset)
+                .limit(query.limit)
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+
+    let emails: Vec<String> = profiles
+        .iter()
+        .map(|profile| profile.public_profile.id.email.to_string())
+        .collect();
+    let accounts = Account::find()
+        .filter(account::Column::Email.is_in(emails.clone()))
+        .all(&state.sql_db)
+        .await?;
+    let grants = RoleGrant::find()
+        .filter(role_grant::Column::Email.is_in(emails))
+        .all(&state.sql_db)
+        .await?;
+    let mut res = Vec::new();
+    for profile in profiles {
+        let email = profile.public_profile.id.email.to_string();
+        let Some(account) = accounts.iter().find(|account| account.email == email) else {
+            continue;
+        };
+        res.push(AccountInfo {
+            id: profile.public_profile.id._id,
+            name: profile.public_profile.name,
+            is_verified: account.is_verified,
+            is_active: account.is_active,
+            totp_enabled: account.totp_enabled,
+            roles: grants
+                .iter()
+                .filter(|grant| grant.email == email)
+                .cloned()
+                .map(GrantInfo::try_from)
+                .collect::<Result<_, _>>()?,
+            created_at: account.created_at,
+            email,
+        });
+    }
+    Ok((query.pagenate(count), Json(res)))
+}
+
+#[debug_handler]
+async fn appoint(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(email): Path<lettre::Address>,
+) -> Result<StatusCode, AppError> {
+    find_administrator(&auth_info, &state).await?;
+    find_account(&state, &email).await?;
+    let grant = role::grant(&state, &email, Role::Administrator, Scope::Global).await?;
+    role::audit_grants(
+        &state,
+        auth_info.id()?,
+        AuditAction::RoleGranted,
+        vec![grant],
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[debug_handler]
+async fn dismiss(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(email): Path<lettre::Address>,
+) -> Result<StatusCode, AppError> {
+    find_administrator(&auth_info, &state).await?;
+    find_account(&state, &email).await?;
+    let grants = role::revoke(&state, &email, Role::Administrator, Scope::Global).await?;
+    role::audit_grants(&state, auth_info.id()?, AuditAction::RoleRevoked, grants).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Disabled accounts keep their data but can neither log in nor use open sessions or tokens.
+#[debug_handler]
+async fn activate(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path((email, yes)): Path<(lettre::Address, bool)>,
+) -> Result<StatusCode, AppError> {
+    if find_administrator(&auth_info, &state).await? == email {
+        return Err(AppError::BadRequest(
+            "You cannot change whether your own account is active!".to_string(),
+        ));
+    }
+    let account = find_account(&state, &email).await?;
+    let before = doc! {
+        "is_active": account.is_active
+    };
+    let mut account: ActiveModel = account.into();
+    account.is_active = ActiveValue::Set(yes);
+    account.updated_at = ActiveValue::Set(chrono::Utc::now().naive_utc());
+    account.update(&state.sql_db).await?;
+    audit::record(
+        &state,
+        auth_info.id()?,
+        if yes {
+            AuditAction::AccountEnabled
+        } else {
+            AuditAction::AccountDisabled
+        },
+        &email,
+        Some(before.into()),
+        Some(
+            doc! {
+                "is_active": yes
+            }
+            .into(),
+        ),
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Makes the current password useless and mails the owner a link to choose a new one.
+#[debug_handler]
+async fn reset(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(email): Path<lettre::Address>,
+) -> Result<StatusCode, AppError> {
+    find_administrator(&auth_info, &state).await?;
+    let mut account: ActiveModel = find_account(&state, &email).await?.into();
+    set_password(&state, &mut account, secret::generate()).await?;
+    // A reset account may be compromised; nobody stays logged in or keeps API access.
+    account.sessions_revoked_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+    account.update(&state.sql_db).await?;
+    api_token::revoke_all(&state, &email).await?;
+    password::send_reset(&state, &email).await?;
+    audit::record(
+        &state,
+        auth_info.id()?,
+        AuditAction::PasswordResetForced,
+        &email,
+        None,
+        None,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/accounts", routing::get(gets))
+        .route(
+            "/accounts/:email/administrator",
+            routing::put(appoint).delete(dismiss),
+        )
+        .route("/accounts/:email/active/:yes", routing::patch(activate))
+        .route("/accounts/:email/reset", routing::post(reset))
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeSet;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{debug_handler, routing, Json, Router};
+use sea_orm::prelude::Uuid;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use serde::{Deserialize, Serialize};
+
+use crate::routes::common::auth::{AuthInfo, TokenScope};
+use crate::routes::common::{err::AppError, secret};
+use crate::sql_entities::{api_token, prelude::ApiToken};
+use crate::state::AppState;
+
+use super::find_own_email;
+
+#[derive(Serialize)]
+struct TokenInfo {
+    id: Uuid,
+    name: String,
+    scopes: BTreeSet<TokenScope>,
+    created_at: chrono::NaiveDateTime,
+    expires_at: Option<chrono::NaiveDateTime>,
+    last_used_at: Option<chrono::NaiveDateTime>,
+    revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+impl TryFrom<api_token::Model> for TokenInfo {
+    type Error = AppError;
+
+    fn try_from(value: api_token::Model) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id,
+            name: value.name,
+            scopes: serde_json::from_value(value.scopes)?,
+            created_at: value.created_at,
+            expires_at: value.expires_at,
+            last_used_at: value.last_used_at,
+            revoked_at: value.revoked_at,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct IssueBody {
+    name: String,
+    #[serde(default)]
+    scopes: BTreeSet<TokenScope>,
+    #[serde(default)]
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+struct IssuedToken {
+    id: Uuid,
+    token: String,
+}
+
+#[debug_handler]
+async fn issue(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Json(body): Json<IssueBody>,
+) -> Result<(StatusCode, Json<IssuedToken>), AppError> {
+    let email = find_own_email(&auth_info, &state).await?;
+    if matches!(body.expires_at, Some(expires_at) if expires_at <= chrono::Utc::now()) {
+        return Err(AppError::BadRequest(
+            "Expiry time has already passed!".to_string(),
+        ));
+    }
+
+    let token = secret::generate();
+    let res = ApiToken::insert(api_token::ActiveModel {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        email: ActiveValue::Set(email.to_string()),
+        name: ActiveValue::Set(body.name),
+        token_hash: ActiveValue::Set(secret::digest(&token)),
+        scopes: ActiveValue::Set(serde_json::to_value(body.scopes)?),
+        created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+        expires_at: ActiveValue::Set(body.expires_at.map(|expires_at| expires_at.naive_utc())),
+        last_used_at: ActiveValue::Set(None),
+        revoked_at: ActiveValue::Set(None),
+    })
+    .exec(&state.sql_db)
+    .await?
+    .last_insert_id;
+    Ok((StatusCode::CREATED, Json(IssuedToken { id: res, token })))
+}
+
+#[debug_handler]
+async fn list(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TokenInfo>>, AppError> {
+    let email = find_own_email(&auth_info, &state).await?;
+    let res = ApiToken::find()
+        .filter(api_token::Column::Email.eq(email.to_string()))
+        .order_by_desc(api_token::Column::CreatedAt)
+        .all(&state.sql_db)
+        .await?
+        .into_iter()
+        .map(TokenInfo::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok(Json(res))
+}
+
+#[debug_handler]
+async fn revoke(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<TokenInfo>, AppError> {
+    let email = find_own_email(&auth_info, &state).await?;
+    let mut token: api_token::ActiveModel = ApiToken::find_by_id(id)
+        .filter(api_token::Column::Email.eq(email.to_string()))
+        .one(&state.sql_db)
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "API token with id {} does not exist!",
+            id
+        )))?
+        .into();
+    if token.revoked_at.as_ref().is_none() {
+        token.revoked_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+    }
+    let res = token.update(&state.sql_db).await?.try_into()?;
+    Ok(Json(res))
+}
+
+/// Revokes every token of `email` that is still usable.
+pub(super) async fn revoke_all(state: &AppState, email: &lettre::Address) -> Result<(), AppError> {
+    ApiToken::update_many()
+        .col_expr(
+            api_token::Column::RevokedAt,
+            Expr::value(chrono::Utc::now().naive_utc()),
+        )
+        .filter(api_token::Column::Email.eq(email.to_string()))
+        .filter(api_token::Column::RevokedAt.is_null())
+        .exec(&state.sql_db)
+        .await?;
+    Ok(())
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/tokens", routing::post(issue).get(list))
+        .route("/tokens/:id", routing::delete(revoke))
+}
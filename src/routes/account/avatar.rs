@@ -0,0 +1,226 @@
+use std::io::Cursor;
+
+use axum::extract::{Multipart, Path, State};
+use axum::http::{header, HeaderName, HeaderValue, StatusCode};
+use axum::{debug_handler, routing, Router};
+use futures::AsyncReadExt;
+use futures_util::{StreamExt, TryStreamExt};
+use image::{imageops::FilterType, ImageFormat};
+use mongodm::mongo::options::{GridFsFindOptions, GridFsUploadOptions};
+use mongodm::prelude::{ObjectId, Set};
+use mongodm::{doc, field, ToRepository};
+
+use crate::mongo_entities::profile::{Profile, ProfileId};
+use crate::routes::common::auth::AuthInfo;
+use crate::routes::common::err::AppError;
+use crate::state::AppState;
+
+use super::profile;
+
+/// Side lengths of the square variants, the first of which is the one `avatar_id` points at.
+const SIZES: [u32; 3] = [256, 64, 32];
+
+fn render(content: &[u8]) -> Result<Vec<(u32, Vec<u8>)>, AppError> {
+    let format = image::guess_format(content)
+        .ok()
+        .filter(|format| {
+            matches!(
+                format,
+                ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP
+            )
+        })
+        .ok_or(AppError::BadRequest(
+            "Avatar is neither PNG, JPEG nor WebP!".to_string(),
+        ))?;
+    let image = image::load_from_memory_with_format(content, format)
+        .map_err(|_| AppError::BadRequest("Avatar cannot be decoded!".to_string()))?;
+    let side = image.width().min(image.height());
+    let image = image.crop_imm(
+        (image.width() - side) / 2,
+        (image.height() - side) / 2,
+        side,
+        side,
+    );
+    SIZES
+        .iter()
+        .map(|size| {
+            let mut res = Cursor::new(Vec::new());
+            image
+                .resize_exact(*size, *size, FilterType::Lanczos3)
+                .write_to(&mut res, ImageFormat::Png)?;
+            Ok((*size, res.into_inner()))
+        })
+        .collect()
+}
+
+/// Deletes an avatar together with all of its variants.
+pub(super) async fn remove(state: &AppState, avatar_id: ObjectId) -> Result<(), AppError> {
+    let bucket = state.mongo_db.gridfs_bucket(None);
+    let ids: Vec<_> = bucket
+        .find(
+            doc! {
+                "$or": [
+                    { "_id": avatar_id },
+                    { "metadata.variant_of": avatar_id }
+                ]
+            },
+            None,
+        )
+        .await?
+        .map_ok(|file| file.id)
+        .try_collect()
+        .await?;
+    for id in ids {
+        bucket.delete(id).await?;
+    }
+    Ok(())
+}
+
+async fn set_avatar_id(
+    state: &AppState,
+    id: ObjectId,
+    avatar_id: Option<ObjectId>,
+) -> Result<(), AppError> {
+    let old = profile::try_find_profile_by_id(state, id)
+        .await?
+        .ok_or(anyhow::anyhow!("Your profile {} has been lost!", id))?
+        .public_profile
+        .id
+        .avatar_id;
+    state
+        .mongo_db
+        .repository::<Profile>()
+        .update_one(
+            doc! {
+                "_id": id
+            },
+            doc! {
+                Set: {
+                    field!(avatar_id in ProfileId): avatar_id
+                }
+            },
+            None,
+        )
+        .await?;
+    if let Some(old) = old {
+        remove(state, old).await?;
+    }
+    Ok(())
+}
+
+#[debug_handler]
+async fn upload(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    mut body: Multipart,
+) -> Result<(StatusCode, axum::Json<ObjectId>), AppError> {
+    let id = auth_info.id()?;
+    let mut field = body
+        .next_field()
+        .await
+        .map_err(anyhow::Error::from)?
+        .ok_or(AppError::BadRequest("No avatar!".to_string()))?;
+    let mut content = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(anyhow::Error::from)? {
+        content.extend_from_slice(&chunk);
+        if content.len() > state.avatar_max_size {
+            return Err(AppError::BadRequest(format!(
+                "Avatar is larger than {} bytes!",
+                state.avatar_max_size
+            )));
+        }
+    }
+    let variants = tokio::task::spawn_blocking(move || render(&content)).await??;
+
+    let bucket = state.mongo_db.gridfs_bucket(None);
+    let mut avatar_id = None;
+    for (size, content) in variants {
+        let mut metadata = doc! {
+            "content-type": mime::IMAGE_PNG.as_ref(),
+            "size": size
+        };
+        if let Some(avatar_id) = avatar_id {
+            metadata.insert("variant_of", avatar_id);
+        }
+        let file_id = bucket
+            .upload_from_futures_0_3_reader(
+                format!("avatar-{}-{}.png", id, size),
+                futures::io::Cursor::new(content),
+                GridFsUploadOptions::builder().metadata(metadata).build(),
+            )
+            .await?;
+        avatar_id.get_or_insert(file_id);
+    }
+    let avatar_id = avatar_id.ok_or(anyhow::anyhow!("No avatar variant was rendered!"))?;
+    set_avatar_id(&state, id, Some(avatar_id)).await?;
+    Ok((StatusCode::CREATED, axum::Json(avatar_id)))
+}
+
+#[debug_handler]
+async fn delete(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    set_avatar_id(&state, auth_info.id()?, None).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[debug_handler]
+async fn get(
+    _auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path((avatar_id, size)): Path<(ObjectId, u32)>,
+) -> Result<([(HeaderName, HeaderValue); 2], Vec<u8>), AppError> {
+    if !SIZES.contains(&size) {
+        return Err(AppError::NotFound(format!(
+            "Avatars come in sizes {:?} only!",
+            SIZES
+        )));
+    }
+
+    let bucket = state.mongo_db.gridfs_bucket(None);
+    let file = bucket
+        .find(
+            doc! {
+                "$or": [
+                    { "_id": avatar_id },
+                    { "metadata.variant_of": avatar_id }
+                ],
+                "metadata.size": size
+            },
+            GridFsFindOptions::builder().limit(1).build(),
+        )
+        .await?
+        .next()
+        .await
+        .ok_or(AppError::NotFound(format!(
+            "Avatar with id {} does not exist!",
+            avatar_id
+        )))??;
+    let mut res = Vec::new();
+    bucket
+        .open_download_stream(file.id)
+        .await?
+        .read_to_end(&mut res)
+        .await?;
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(mime::IMAGE_PNG.as_ref()),
+            ),
+            // A replaced avatar gets a new id, so whatever is served under one never changes.
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=31536000, immutable"),
+            ),
+        ],
+        res,
+    ))
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/profile/avatar", routing::put(upload).delete(delete))
+        .route("/avatars/:id/:size", routing::get(get))
+}
@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use axum::{
     debug_handler,
     extract::{Path, State},
@@ -5,26 +7,71 @@ use axum::{
     routing, Json, Router,
 };
 use mongodm::{bson::to_bson, doc, field, prelude::ObjectId, ToRepository};
-use sea_orm::{prelude::Uuid, ActiveModelTrait, ActiveValue, EntityTrait};
+use sea_orm::{prelude::Uuid, ActiveModelTrait, ActiveValue, EntityTrait, ModelTrait};
 use serde::Deserialize;
 
 use crate::{
-    mongo_entities::profile::{Profile, ProfileId},
+    mongo_entities::{
+        audit::AuditAction,
+        profile::{Profile, ProfileId},
+    },
     sql_entities::{
         account::{self, ActiveModel},
         prelude::Account,
+        sea_orm_active_enums::{HashAlgorithm, Role, TokenPurpose},
     },
     state::AppState,
 };
 
-use super::common::{auth::AuthInfoStorage, err::AppError};
+use super::common::{
+    auth::{self, AuthInfo, AuthInfoStorage, Permission, Scope},
+    client_ip::ClientIp,
+    err::AppError,
+};
 
+mod admin;
+mod api_token;
+mod avatar;
+mod oidc;
+mod password;
+mod personal_data;
 mod profile;
+mod role;
+mod throttle;
+mod token;
+mod totp;
+mod verify;
 
-async fn get_hash(cost: u8, salt: [u8; 16], password: String) -> Result<[u8; 24], AppError> {
-    tokio::task::spawn_blocking(move || passwords::hasher::bcrypt(cost, &salt, &password))
-        .await?
-        .map_err(|e| AppError::AnyHow(anyhow::anyhow!(e)))
+/// The algorithm new password hashes are produced with.
+const HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Bcrypt;
+
+async fn get_hash(
+    algorithm: HashAlgorithm,
+    cost: u8,
+    salt: [u8; 16],
+    password: String,
+) -> Result<Vec<u8>, AppError> {
+    tokio::task::spawn_blocking(move || match algorithm {
+        HashAlgorithm::Bcrypt => passwords::hasher::bcrypt(cost, &salt, &password).map(Vec::from),
+    })
+    .await?
+    .map_err(|e| AppError::AnyHow(anyhow::anyhow!(e)))
+}
+
+/// Re-salts and re-hashes `password` with the current algorithm and cost.
+async fn set_password(
+    state: &AppState,
+    account: &mut ActiveModel,
+    password: String,
+) -> Result<(), AppError> {
+    let salt = passwords::hasher::gen_salt();
+    account.salt = ActiveValue::Set(Uuid::from_bytes(salt));
+    account.password_hash =
+        ActiveValue::Set(get_hash(HASH_ALGORITHM, state.hash_cost, salt, password).await?);
+    account.hash_algorithm = ActiveValue::Set(HASH_ALGORITHM);
+    account.hash_cost = ActiveValue::Set(state.hash_cost.into());
+    account.updated_at = ActiveValue::Set(chrono::Utc::now().naive_utc());
+    Ok(())
 }
 
 async fn try_find_account(
@@ -54,6 +101,19 @@ async fn try_find_profile(
     Ok(res)
 }
 
+/// Sensitive settings cannot be reached through an API token.
+pub(super) async fn find_own_email(
+    auth_info: &AuthInfo,
+    state: &AppState,
+) -> Result<lettre::Address, AppError> {
+    if auth_info.by_token {
+        return Err(AppError::Forbidden(
+            "This can only be done from a logged-in session!".to_string(),
+        ));
+    }
+    profile::find_email(state, auth_info.id()?).await
+}
+
 #[derive(Deserialize)]
 struct SignupBody {
     password: String,
@@ -64,14 +124,18 @@ struct SignupBody {
 #[debug_handler]
 async fn signup(
     State(state): State<AppState>,
-    Json(mut body): Json<SignupBody>,
+    Json(body): Json<SignupBody>,
 ) -> Result<(StatusCode, Json<ObjectId>), AppError> {
-    let account = try_find_account(&state, &body.profile.public_profile.id.email).await?;
-    if account.is_some() {
-        return Err(AppError::Conflict(format!(
-            "Account with {} already exists!",
-            body.profile.public_profile.id.email
-        )));
+    let email = body.profile.public_profile.id.email.clone();
+    if let Some(account) = try_find_account(&state, &email).await? {
+        // An unconfirmed account whose link has expired must not keep the address from its owner.
+        if account.is_verified || token::is_pending(&state, &email, TokenPurpose::Verify).await? {
+            return Err(AppError::Conflict(format!(
+                "Account with {} already exists!",
+                email
+            )));
+        }
+        discard_account(&state, account).await?;
     }
     if try_find_profile(&state, &body.profile.public_profile.id.email)
         .await?
@@ -83,60 +147,126 @@ async fn signup(
         )
         .into());
     }
-    let salt = passwords::hasher::gen_salt();
-    let account = ActiveModel {
+    let is_first = Account::find().all(&state.sql_db).await?.is_empty();
+    let mut account = ActiveModel {
         email: ActiveValue::Set(body.profile.public_profile.id.email.clone().to_string()),
-        salt: ActiveValue::Set(Uuid::from_bytes(salt)),
-        password_hash: ActiveValue::Set(
-            get_hash(state.hash_cost, salt, body.password).await?.into(),
-        ),
-        is_administrator: ActiveValue::Set(Account::find().all(&state.sql_db).await?.is_empty()),
         created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
-        updated_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
-        is_editor: ActiveValue::Set(false),
+        is_verified: ActiveValue::Set(false),
+        is_active: ActiveValue::Set(true),
+        ..Default::default()
     };
-    Account::insert(account).exec(&state.sql_db).await?;
+    set_password(&state, &mut account, body.password).await?;
+    let account = Account::insert(account)
+        .exec_with_returning(&state.sql_db)
+        .await?;
+    match create_profile(&state, &email, body.profile, is_first).await {
+        Ok(res) => Ok((StatusCode::CREATED, Json(res))),
+        Err(e) => {
+            // An account nobody can confirm must not keep the address from a retry.
+            discard_account(&state, account).await?;
+            Err(e)
+        }
+    }
+}
+
+async fn create_profile(
+    state: &AppState,
+    email: &lettre::Address,
+    mut profile: Profile,
+    is_first: bool,
+) -> Result<ObjectId, AppError> {
+    if is_first {
+        role::grant(state, email, Role::Administrator, Scope::Global).await?;
+    }
 
-    body.profile.public_profile.id._id = ObjectId::new();
-    body.profile.public_profile.id.avatar_id = None;
-    body.profile.public_profile.id.joining_at = chrono::Utc::now();
+    profile.public_profile.id._id = ObjectId::new();
+    profile.public_profile.id.avatar_id = None;
+    profile.public_profile.id.joining_at = chrono::Utc::now();
     let res = state
         .mongo_db
         .repository::<Profile>()
-        .insert_one(body.profile, None)
+        .insert_one(profile, None)
         .await?
         .inserted_id
         .as_object_id()
         .ok_or(AppError::AnyHow(anyhow::anyhow!("Cannot get inserted id!")))?;
-    Ok((StatusCode::CREATED, Json(res)))
+    verify::send_verification(state, email).await?;
+    Ok(res)
+}
+
+/// Removes an account together with its profile; tokens and grants go with the account.
+async fn discard_account(state: &AppState, account: account::Model) -> Result<(), AppError> {
+    let email: lettre::Address = account.email.parse()?;
+    account.delete(&state.sql_db).await?;
+    state
+        .mongo_db
+        .repository::<Profile>()
+        .delete_one(
+            doc! {
+                field!(email in ProfileId): to_bson(&email)?
+            },
+            None,
+        )
+        .await?;
+    Ok(())
 }
 
 #[derive(Deserialize)]
 struct AppointBody {
     email: lettre::Address,
     auth: AuthBody,
+    #[serde(default)]
+    code: Option<String>,
 }
 
 #[debug_handler]
 async fn appoint(
+    ClientIp(ip): ClientIp,
     State(state): State<AppState>,
     Path(yes): Path<bool>,
     Json(body): Json<AppointBody>,
 ) -> Result<(), AppError> {
-    if !auth(&state, &body.auth).await?.is_administrator {
+    let administrator = auth(&state, ip, &body.auth).await?;
+    if administrator.totp_enabled {
+        totp::check_code(
+            &state,
+            ip,
+            administrator.clone(),
+            body.code.as_deref().unwrap_or_default(),
+        )
+        .await?;
+    } else if state.totp_required {
         return Err(AppError::Forbidden(
-            "You are not an administrator!".to_string(),
+            "Enable TOTP before managing accounts!".to_string(),
         ));
     }
-    let mut account: ActiveModel = try_find_account(&state, &body.email)
+    if !auth::load_permissions(&state, &administrator.email)
         .await?
-        .ok_or(AppError::NotFound(format!(
+        .contains(&(Permission::Managing, Scope::Global))
+    {
+        return Err(AppError::Forbidden(
+            "You are not an administrator!".to_string(),
+        ));
+    }
+    if try_find_account(&state, &body.email).await?.is_none() {
+        return Err(AppError::NotFound(format!(
             "Account with email {} does not exist!",
             body.email
-        )))?
-        .into();
-    account.is_editor = ActiveValue::Set(yes);
-    account.update(&state.sql_db).await?;
+        )));
+    }
+    let actor_id = try_find_profile(&state, &body.auth.email)
+        .await?
+        .ok_or(anyhow::anyhow!("Your profile has been lost!"))?
+        .public_profile
+        .id
+        ._id;
+    if yes {
+        let grant = role::grant(&state, &body.email, Role::Editor, Scope::Global).await?;
+        role::audit_grants(&state, actor_id, AuditAction::RoleGranted, vec![grant]).await?;
+    } else {
+        let grants = role::revoke(&state, &body.email, Role::Editor, Scope::Global).await?;
+        role::audit_grants(&state, actor_id, AuditAction::RoleRevoked, grants).await?;
+    }
     Ok(())
 }
 
@@ -148,43 +278,93 @@ struct AuthBody {
 
 #[debug_handler]
 async fn login(
+    ClientIp(ip): ClientIp,
     mut auth_info_storage: AuthInfoStorage,
     State(state): State<AppState>,
     Json(body): Json<AuthBody>,
-) -> Result<Json<Profile>, AppError> {
-    let account = auth(&state, &body).await?;
+) -> Result<(StatusCode, Json<Option<Profile>>), AppError> {
+    let account = auth(&state, ip, &body).await?;
+    if account.totp_enabled {
+        totp::defer_login(&mut auth_info_storage, body.email)?;
+        return Ok((StatusCode::ACCEPTED, Json(None)));
+    }
+
+    let res = finish_login(&state, &mut auth_info_storage, account).await?;
+    Ok((StatusCode::OK, Json(Some(res))))
+}
 
-    let res = try_find_profile(&state, &body.email)
+async fn finish_login(
+    state: &AppState,
+    auth_info_storage: &mut AuthInfoStorage,
+    account: account::Model,
+) -> Result<Profile, AppError> {
+    let res = try_find_profile(state, &account.email.parse()?)
         .await?
         .ok_or(AppError::AnyHow(anyhow::anyhow!(
             "Your profile has been lost!"
         )))?;
-    auth_info_storage.store(
-        res.public_profile.id._id,
-        account.is_administrator,
-        account.is_editor,
-    )?;
-    Ok(Json(res))
+    auth_info_storage.store(res.public_profile.id._id, account.email)?;
+    Ok(res)
 }
 
-async fn auth(state: &AppState, body: &AuthBody) -> Result<account::Model, AppError> {
-    let account = try_find_account(state, &body.email)
-        .await?
-        .ok_or(AppError::NotFound(format!(
-            "Account with email {} does not exist!",
+fn disabled(email: &lettre::Address) -> AppError {
+    AppError::Forbidden(format!("Account with email {} has been disabled!", email))
+}
+
+async fn auth(state: &AppState, ip: IpAddr, body: &AuthBody) -> Result<account::Model, AppError> {
+    throttle::check(state, &body.email, ip).await?;
+    let mut account = match verify_password(state, body).await? {
+        Some(account) => account,
+        None => {
+            throttle::fail(state, &body.email, ip).await?;
+            // Unknown accounts and wrong passwords must look alike.
+            return Err(AppError::BadRequest("Wrong email or password!".to_string()));
+        }
+    };
+    throttle::clear(state, &body.email).await?;
+    if !account.is_verified {
+        return Err(AppError::Forbidden(format!(
+            "Email address {} has not been confirmed yet!",
             body.email
-        )))?;
-    let salt = account.salt.into_bytes();
-    if get_hash(state.hash_cost, salt, body.password.clone())
-        .await?
-        .to_vec()
-        != account.password_hash
-    {
-        return Err(AppError::BadRequest("Wrong password!".to_string()));
+        )));
+    }
+    if !account.is_active {
+        return Err(disabled(&body.email));
+    }
+    if account.hash_algorithm != HASH_ALGORITHM || account.hash_cost < state.hash_cost.into() {
+        let mut active: ActiveModel = account.into();
+        set_password(state, &mut active, body.password.clone()).await?;
+        account = active.update(&state.sql_db).await?;
     }
     Ok(account)
 }
 
+async fn verify_password(
+    state: &AppState,
+    body: &AuthBody,
+) -> Result<Option<account::Model>, AppError> {
+    let Some(account) = try_find_account(state, &body.email).await? else {
+        // Spend as long as a real comparison would.
+        get_hash(
+            HASH_ALGORITHM,
+            state.hash_cost,
+            passwords::hasher::gen_salt(),
+            body.password.clone(),
+        )
+        .await?;
+        return Ok(None);
+    };
+    // Verify against whatever produced the stored hash, not against the current settings.
+    let hash = get_hash(
+        account.hash_algorithm.clone(),
+        account.hash_cost.try_into()?,
+        account.salt.into_bytes(),
+        body.password.clone(),
+    )
+    .await?;
+    Ok((hash == account.password_hash).then_some(account))
+}
+
 #[debug_handler]
 async fn logout(mut auth_info_storage: AuthInfoStorage) {
     auth_info_storage.destroy()
@@ -192,6 +372,16 @@ async fn logout(mut auth_info_storage: AuthInfoStorage) {
 
 pub(super) fn new() -> Router<AppState> {
     profile::new()
+        .merge(verify::new())
+        .merge(password::new())
+        .merge(api_token::new())
+        .merge(totp::new())
+        .merge(throttle::new())
+        .merge(role::new())
+        .merge(personal_data::new())
+        .merge(oidc::new())
+        .merge(admin::new())
+        .merge(avatar::new())
         .route("/signup", routing::post(signup))
         .route("/appoint/:yes", routing::patch(appoint))
         .route("/login", routing::post(login))
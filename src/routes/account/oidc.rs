@@ -0,0 +1,282 @@
+use axum::extract::{Query, State};
+use axum::response::Redirect;
+use axum::{debug_handler, routing, Router};
+use mongodm::prelude::ObjectId;
+use mongodm::ToRepository;
+use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata};
+use openidconnect::reqwest::async_http_client;
+use openidconnect::{
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, IssuerUrl, Nonce, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, TokenResponse,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait};
+use serde::{Deserialize, Serialize};
+
+use crate::mongo_entities::profile::{Profile, ProfileId, PublicProfile};
+use crate::routes::common::auth::AuthInfoStorage;
+use crate::routes::common::{err::AppError, secret};
+use crate::routes::invitation;
+use crate::sql_entities::{
+    account::{self, ActiveModel},
+    oidc_identity,
+    prelude::{Account, OidcIdentity},
+    sea_orm_active_enums::TokenPurpose,
+};
+use crate::state::AppState;
+
+use super::{
+    disabled, finish_login, set_password, token, totp, try_find_account, try_find_profile,
+};
+
+const PENDING_SSO: &str = "pending_sso";
+const PENDING_SSO_TTL: i64 = 10 * 60;
+
+/// What has to survive the round trip through the issuer.
+#[derive(Serialize, Deserialize)]
+struct PendingSso {
+    state: String,
+    nonce: String,
+    verifier: String,
+    expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Discovers the issuer afresh so that it may be restarted or rotate its keys at will.
+async fn client(state: &AppState) -> Result<(String, CoreClient), AppError> {
+    let issuer = state
+        .oidc_issuer
+        .as_ref()
+        .ok_or(AppError::NotFound(
+            "Single sign-on is not configured!".to_string(),
+        ))?
+        .to_string();
+    let client = discover(
+        &issuer,
+        &state.oidc_client_id,
+        state.oidc_client_secret.as_deref().map(String::as_str),
+        &state.oidc_redirect_url,
+    )
+    .await?;
+    Ok((issuer, client))
+}
+
+async fn discover(
+    issuer: &str,
+    client_id: &str,
+    client_secret: Option<&str>,
+    redirect_url: &str,
+) -> Result<CoreClient, AppError> {
+    let metadata = CoreProviderMetadata::discover_async(
+        IssuerUrl::new(issuer.to_string())?,
+        async_http_client,
+    )
+    .await?;
+    let client = CoreClient::from_provider_metadata(
+        metadata,
+        ClientId::new(client_id.to_string()),
+        client_secret.map(|secret| ClientSecret::new(secret.to_string())),
+    )
+    .set_redirect_uri(RedirectUrl::new(redirect_url.to_string())?);
+    Ok(client)
+}
+
+/// Who the issuer vouches for once the code is exchanged.
+#[derive(Debug)]
+struct Identity {
+    subject: String,
+    email: lettre::Address,
+    name: String,
+}
+
+/// Exchanges `code` and checks the ID token that comes back.
+async fn identify(
+    client: &CoreClient,
+    code: String,
+    verifier: String,
+    nonce: String,
+) -> Result<Identity, AppError> {
+    let response = client
+        .exchange_code(AuthorizationCode::new(code))
+        .set_pkce_verifier(PkceCodeVerifier::new(verifier))
+        .request_async(async_http_client)
+        .await?;
+    let claims = response
+        .id_token()
+        .ok_or(AppError::Forbidden(
+            "The identity provider sent no ID token!".to_string(),
+        ))?
+        .claims(&client.id_token_verifier(), &Nonce::new(nonce))?;
+    let email: lettre::Address = claims
+        .email()
+        .filter(|_| claims.email_verified() == Some(true))
+        .ok_or(AppError::Forbidden(
+            "The identity provider has not verified your email address!".to_string(),
+        ))?
+        .to_string()
+        .parse()?;
+    let name = claims
+        .name()
+        .and_then(|name| name.get(None))
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| email.user().to_string());
+    Ok(Identity {
+        subject: claims.subject().to_string(),
+        email,
+        name,
+    })
+}
+
+#[debug_handler]
+async fn login(
+    mut auth_info_storage: AuthInfoStorage,
+    State(state): State<AppState>,
+) -> Result<Redirect, AppError> {
+    let (_, client) = client(&state).await?;
+    let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+    let (url, csrf_token, nonce) = client
+        .authorize_url(
+            CoreAuthenticationFlow::AuthorizationCode,
+            CsrfToken::new_random,
+            Nonce::new_random,
+        )
+        .add_scope(openidconnect::Scope::new("email".to_string()))
+        .add_scope(openidconnect::Scope::new("profile".to_string()))
+        .set_pkce_challenge(challenge)
+        .url();
+    auth_info_storage.insert(
+        PENDING_SSO,
+        PendingSso {
+            state: csrf_token.secret().to_string(),
+            nonce: nonce.secret().to_string(),
+            verifier: verifier.secret().to_string(),
+            expires_at: chrono::Utc::now() + chrono::Duration::seconds(PENDING_SSO_TTL),
+        },
+    )?;
+    Ok(Redirect::to(url.as_str()))
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    code: String,
+    state: String,
+}
+
+#[debug_handler]
+async fn callback(
+    mut auth_info_storage: AuthInfoStorage,
+    State(state): State<AppState>,
+    Query(query): Query<CallbackQuery>,
+) -> Result<Redirect, AppError> {
+    let pending = auth_info_storage
+        .get::<PendingSso>(PENDING_SSO)
+        .filter(|pending| pending.expires_at > chrono::Utc::now() && pending.state == query.state)
+        .ok_or(AppError::Forbidden(
+            "Single sign-on was not started from here!".to_string(),
+        ))?;
+    auth_info_storage.remove(PENDING_SSO);
+
+    let (issuer, client) = client(&state).await?;
+    let Identity {
+        subject,
+        email,
+        name,
+    } = identify(&client, query.code, pending.verifier, pending.nonce).await?;
+
+    let account = match OidcIdentity::find_by_id((issuer.clone(), subject.clone()))
+        .one(&state.sql_db)
+        .await?
+    {
+        Some(identity) => Account::find_by_id(identity.email)
+            .one(&state.sql_db)
+            .await?
+            .ok_or(anyhow::anyhow!("Account of {} has been lost!", subject))?,
+        None => {
+            let account = link(&state, &email, name).await?;
+            OidcIdentity::insert(oidc_identity::ActiveModel {
+                issuer: ActiveValue::Set(issuer),
+                subject: ActiveValue::Set(subject),
+                email: ActiveValue::Set(email.to_string()),
+                linked_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            })
+            .exec(&state.sql_db)
+            .await?;
+            account
+        }
+    };
+
+    if !account.is_active {
+        return Err(disabled(&email));
+    }
+    if account.totp_enabled {
+        totp::defer_login(&mut auth_info_storage, account.email.parse()?)?;
+        return Ok(Redirect::to(&format!("{}/login/totp", state.clt_addr)));
+    }
+    finish_login(&state, &mut auth_info_storage, account).await?;
+    Ok(Redirect::to(&state.clt_addr))
+}
+
+/// Finds the account with `email` or creates one, trusting the issuer to have verified the address.
+async fn link(
+    state: &AppState,
+    email: &lettre::Address,
+    name: String,
+) -> Result<account::Model, AppError> {
+    let account = match try_find_account(state, email).await? {
+        Some(account) if account.is_verified => account,
+        Some(account) => {
+            // Whoever signed up with this address without confirming it must not keep a password.
+            token::revoke_all(state, email, TokenPurpose::Verify).await?;
+            let mut account: ActiveModel = account.into();
+            set_password(state, &mut account, secret::generate()).await?;
+            account.is_verified = ActiveValue::Set(true);
+            account.update(&state.sql_db).await?
+        }
+        None => {
+            let mut account = ActiveModel {
+                email: ActiveValue::Set(email.to_string()),
+                created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+                is_verified: ActiveValue::Set(true),
+                is_active: ActiveValue::Set(true),
+                ..Default::default()
+            };
+            // Unknown to its owner; a password can be set through the reset flow.
+            set_password(state, &mut account, secret::generate()).await?;
+            account.insert(&state.sql_db).await?
+        }
+    };
+    let id = match try_find_profile(state, email).await? {
+        Some(profile) => profile.public_profile.id._id,
+        None => state
+            .mongo_db
+            .repository::<Profile>()
+            .insert_one(
+                Profile {
+                    public_profile: PublicProfile {
+                        id: ProfileId {
+                            _id: ObjectId::new(),
+                            email: email.clone(),
+                            avatar_id: None,
+                            joining_at: chrono::Utc::now(),
+                        },
+                        name,
+                    },
+                    setting: Default::default(),
+                },
+                None,
+            )
+            .await?
+            .inserted_id
+            .as_object_id()
+            .ok_or(anyhow::anyhow!("Cannot get inserted id!"))?,
+    };
+    invitation::resolve_all(state, email, id).await?;
+    Ok(account)
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/oidc/login", routing::get(login))
+        .route("/oidc/callback", routing::get(callback))
+}
+
+#[cfg(test)]
+mod tests;
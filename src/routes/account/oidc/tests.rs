@@ -0,0 +1,162 @@
+use std::net::TcpListener;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::{routing, Form, Json, Router};
+use openidconnect::core::{
+    CoreIdToken, CoreIdTokenClaims, CoreIdTokenFields, CoreJsonWebKeySet, CoreJwsSigningAlgorithm,
+    CoreProviderMetadata, CoreResponseType, CoreRsaPrivateSigningKey, CoreSubjectIdentifierType,
+    CoreTokenResponse, CoreTokenType,
+};
+use openidconnect::{
+    AccessToken, Audience, AuthUrl, EmptyAdditionalClaims, EmptyAdditionalProviderMetadata,
+    EmptyExtraTokenFields, EndUserEmail, EndUserName, IssuerUrl, JsonWebKeyId, JsonWebKeySetUrl,
+    Nonce, PrivateSigningKey, ResponseTypes, StandardClaims, SubjectIdentifier, TokenUrl,
+};
+use serde::Deserialize;
+
+use crate::routes::common::err::AppError;
+
+use super::{discover, identify};
+
+const CLIENT_ID: &str = "prepublish";
+const REDIRECT_URL: &str = "http://127.0.0.1:8000/oidc/callback";
+const CODE: &str = "code";
+const VERIFIER: &str = "0123456789abcdefghijklmnopqrstuvwxyzABCDEFG";
+const NONCE: &str = "nonce";
+
+/// What the mock issuer puts into the ID tokens it hands out.
+#[derive(Clone)]
+struct Issuer {
+    url: String,
+    nonce: String,
+    email_verified: bool,
+}
+
+fn signing_key() -> CoreRsaPrivateSigningKey {
+    CoreRsaPrivateSigningKey::from_pem(
+        include_str!("signing_key.pem"),
+        Some(JsonWebKeyId::new("test".to_string())),
+    )
+    .unwrap()
+}
+
+async fn metadata(State(issuer): State<Issuer>) -> Json<CoreProviderMetadata> {
+    Json(
+        CoreProviderMetadata::new(
+            IssuerUrl::new(issuer.url.clone()).unwrap(),
+            AuthUrl::new(format!("{}/authorize", issuer.url)).unwrap(),
+            JsonWebKeySetUrl::new(format!("{}/jwks", issuer.url)).unwrap(),
+            vec![ResponseTypes::new(vec![CoreResponseType::Code])],
+            vec![CoreSubjectIdentifierType::Public],
+            vec![CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256],
+            EmptyAdditionalProviderMetadata {},
+        )
+        .set_token_endpoint(Some(
+            TokenUrl::new(format!("{}/token", issuer.url)).unwrap(),
+        )),
+    )
+}
+
+async fn jwks() -> Json<CoreJsonWebKeySet> {
+    Json(CoreJsonWebKeySet::new(vec![
+        signing_key().as_verification_key()
+    ]))
+}
+
+#[derive(Deserialize)]
+struct TokenForm {
+    code: String,
+    code_verifier: String,
+}
+
+async fn token(
+    State(issuer): State<Issuer>,
+    Form(form): Form<TokenForm>,
+) -> Result<Json<CoreTokenResponse>, StatusCode> {
+    if form.code != CODE || form.code_verifier != VERIFIER {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let now = chrono::Utc::now();
+    let claims = CoreIdTokenClaims::new(
+        IssuerUrl::new(issuer.url).unwrap(),
+        vec![Audience::new(CLIENT_ID.to_string())],
+        now + chrono::Duration::minutes(5),
+        now,
+        StandardClaims::new(SubjectIdentifier::new("subject".to_string()))
+            .set_email(Some(EndUserEmail::new("user@example.com".to_string())))
+            .set_email_verified(Some(issuer.email_verified))
+            .set_name(Some(EndUserName::new("User".to_string()).into())),
+        EmptyAdditionalClaims {},
+    )
+    .set_nonce(Some(Nonce::new(issuer.nonce)));
+    let id_token = CoreIdToken::new(
+        claims,
+        &signing_key(),
+        CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+        None,
+        None,
+    )
+    .unwrap();
+    Ok(Json(CoreTokenResponse::new(
+        AccessToken::new("access".to_string()),
+        CoreTokenType::Bearer,
+        CoreIdTokenFields::new(Some(id_token), EmptyExtraTokenFields {}),
+    )))
+}
+
+/// Starts an issuer on a free port and returns its URL.
+fn serve(nonce: &str, email_verified: bool) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let app = Router::new()
+        .route("/.well-known/openid-configuration", routing::get(metadata))
+        .route("/jwks", routing::get(jwks))
+        .route("/token", routing::post(token))
+        .with_state(Issuer {
+            url: url.clone(),
+            nonce: nonce.to_string(),
+            email_verified,
+        });
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service()),
+    );
+    url
+}
+
+async fn login(issuer: &str) -> Result<super::Identity, AppError> {
+    let client = discover(issuer, CLIENT_ID, None, REDIRECT_URL).await?;
+    identify(
+        &client,
+        CODE.to_string(),
+        VERIFIER.to_string(),
+        NONCE.to_string(),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn identifies_verified_users() {
+    let identity = match login(&serve(NONCE, true)).await {
+        Ok(identity) => identity,
+        Err(_) => panic!("Login against the mock issuer failed!"),
+    };
+    assert_eq!(identity.subject, "subject");
+    assert_eq!(identity.email.to_string(), "user@example.com");
+    assert_eq!(identity.name, "User");
+}
+
+#[tokio::test]
+async fn rejects_foreign_nonces() {
+    assert!(login(&serve("replayed", true)).await.is_err());
+}
+
+#[tokio::test]
+async fn rejects_unverified_emails() {
+    assert!(matches!(
+        login(&serve(NONCE, false)).await,
+        Err(AppError::Forbidden(_))
+    ));
+}
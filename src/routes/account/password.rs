@@ -0,0 +1,119 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::{debug_handler, routing, Json, Router};
+use sea_orm::{ActiveModelTrait, ActiveValue};
+use serde::Deserialize;
+
+use crate::routes::common::{
+    auth::{AuthInfo, AuthInfoStorage},
+    client_ip::ClientIp,
+    err::AppError,
+    mail,
+};
+use crate::sql_entities::{account::ActiveModel, sea_orm_active_enums::TokenPurpose};
+use crate::state::AppState;
+
+use super::{api_token, auth, profile, set_password, token, try_find_account, AuthBody};
+
+pub(super) async fn send_reset(state: &AppState, email: &lettre::Address) -> Result<(), AppError> {
+    token::revoke_all(state, email, TokenPurpose::Reset).await?;
+    let token = token::issue(state, email, TokenPurpose::Reset, state.reset_ttl).await?;
+    mail::send(
+        state,
+        email,
+        "Reset your password",
+        format!(
+            "Open the following link to choose a new password:\n\n{}/reset?token={}\n\nThe link expires in {} minutes and can be used only once. If you did not ask for it, just ignore this email.",
+            state.clt_addr,
+            token,
+            state.reset_ttl.num_minutes()
+        ),
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+struct ForgotBody {
+    email: lettre::Address,
+}
+
+#[debug_handler]
+async fn forgot(
+    State(state): State<AppState>,
+    Json(body): Json<ForgotBody>,
+) -> Result<StatusCode, AppError> {
+    if try_find_account(&state, &body.email).await?.is_some() {
+        send_reset(&state, &body.email).await?;
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Deserialize)]
+struct ResetBody {
+    token: String,
+    password: String,
+}
+
+#[debug_handler]
+async fn reset(State(state): State<AppState>, Json(body): Json<ResetBody>) -> Result<(), AppError> {
+    let email = token::redeem(&state, &body.token, TokenPurpose::Reset).await?;
+    let mut account: ActiveModel = try_find_account(&state, &email)
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "Account with email {} does not exist!",
+            email
+        )))?
+        .into();
+    set_password(&state, &mut account, body.password).await?;
+    // Following the link proves the ownership of the address as well.
+    account.is_verified = ActiveValue::Set(true);
+    // Whoever knew the old password must not stay logged in with it.
+    account.sessions_revoked_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+    account.update(&state.sql_db).await?;
+    token::revoke_all(&state, &email, TokenPurpose::Reset).await?;
+    api_token::revoke_all(&state, &email).await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ChangeBody {
+    password: String,
+    new_password: String,
+}
+
+#[debug_handler]
+async fn change(
+    ClientIp(ip): ClientIp,
+    auth_info: AuthInfo,
+    mut auth_info_storage: AuthInfoStorage,
+    State(state): State<AppState>,
+    Json(body): Json<ChangeBody>,
+) -> Result<(), AppError> {
+    let email = profile::find_email(&state, auth_info.id()?).await?;
+    let mut account: ActiveModel = auth(
+        &state,
+        ip,
+        &AuthBody {
+            email: email.clone(),
+            password: body.password,
+        },
+    )
+    .await?
+    .into();
+    set_password(&state, &mut account, body.new_password).await?;
+    account.sessions_revoked_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+    account.update(&state.sql_db).await?;
+    token::revoke_all(&state, &email, TokenPurpose::Reset).await?;
+    // Every other session is logged out; this one logs in again with the new password.
+    if !auth_info.by_token {
+        auth_info_storage.store(auth_info.id()?, email.to_string())?;
+    }
+    Ok(())
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/password", routing::patch(change))
+        .route("/password/forgot", routing::post(forgot))
+        .route("/password/reset", routing::post(reset))
+}
@@ -0,0 +1,498 @@
+use std::io::Write;
+
+use axum::extract::State;
+use axum::http::{header, HeaderName, HeaderValue, StatusCode};
+use axum::{debug_handler, routing, Json, Router};
+use futures::AsyncReadExt;
+use futures_util::{StreamExt, TryStreamExt};
+use mongodm::bson::{to_bson, Bson, DateTime};
+use mongodm::mongo::GridFsBucket;
+use mongodm::prelude::{ObjectId, Pull, Set};
+use mongodm::{bson, doc, field, ToRepository};
+use sea_orm::{ActiveEnum, ColumnTrait, EntityTrait, ModelTrait, QueryFilter, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+
+use crate::mongo_entities::invitation::Invitation;
+use crate::mongo_entities::notification::Notification;
+use crate::mongo_entities::profile::{Profile, ProfileId};
+use crate::mongo_entities::thesis::{Comment, Review, ReviewState, Thesis, ThesisId, Version};
+use crate::routes::common::auth::{AuthInfo, AuthInfoStorage};
+use crate::routes::common::client_ip::ClientIp;
+use crate::routes::common::err::AppError;
+use crate::sql_entities::{
+    api_token,
+    prelude::{ApiToken, RoleGrant},
+    role_grant,
+};
+use crate::state::AppState;
+
+use super::{auth, avatar, find_own_email, profile, role, totp, AuthBody};
+
+const ARCHIVE_NAME: &str = "prepublish-export.zip";
+/// How much of a file is held in memory at a time while it is archived.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize)]
+struct AccountExport {
+    email: String,
+    is_verified: bool,
+    is_active: bool,
+    totp_enabled: bool,
+    created_at: chrono::NaiveDateTime,
+    updated_at: chrono::NaiveDateTime,
+    roles: Vec<RoleExport>,
+    api_tokens: Vec<ApiTokenExport>,
+}
+
+#[derive(Serialize)]
+struct RoleExport {
+    role: String,
+    scope_kind: String,
+    scope_id: Option<String>,
+    granted_at: chrono::NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct ApiTokenExport {
+    name: String,
+    scopes: serde_json::Value,
+    created_at: chrono::NaiveDateTime,
+    expires_at: Option<chrono::NaiveDateTime>,
+    last_used_at: Option<chrono::NaiveDateTime>,
+    revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+async fn collect<M>(state: &AppState, filter: bson::Document) -> Result<Vec<M>, AppError>
+where
+    M: mongodm::Model + Send + Sync + Unpin,
+{
+    let res = state
+        .mongo_db
+        .repository::<M>()
+        .find(filter, None)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(res)
+}
+
+/// Streams the file into `zip` chunk by chunk; runs on a blocking thread, waiting on `handle`.
+fn copy_file(
+    handle: &Handle,
+    bucket: &GridFsBucket,
+    zip: &mut zip::ZipWriter<std::io::Cursor<Vec<u8>>>,
+    id: ObjectId,
+) -> Result<(), AppError> {
+    let Some(file) = handle.block_on(async {
+        bucket
+            .find(
+                doc! {
+                    "_id": id
+                },
+                None,
+            )
+            .await?
+            .next()
+            .await
+            .transpose()
+    })?
+    else {
+        return Ok(());
+    };
+    // Uploaded names are untrusted; only their last component may end up in the archive.
+    let filename = file.filename.unwrap_or_default();
+    let filename = filename
+        .rsplit(['/', '\\'])
+        .find(|component| !component.is_empty() && *component != "." && *component != "..")
+        .unwrap_or_default();
+    zip.start_file(
+        format!("files/{}-{}", id, filename),
+        zip::write::FileOptions::default(),
+    )?;
+    let mut stream = handle.block_on(bucket.open_download_stream(bson!(id)))?;
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = handle.block_on(stream.read(&mut buf))?;
+        if read == 0 {
+            break;
+        }
+        zip.write_all(&buf[..read])?;
+    }
+    Ok(())
+}
+
+fn json<T: Serialize>(name: &str, value: &T) -> Result<(String, Vec<u8>), AppError> {
+    Ok((name.to_string(), serde_json::to_vec_pretty(value)?))
+}
+
+#[debug_handler]
+async fn export(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+) -> Result<([(HeaderName, HeaderValue); 2], Vec<u8>), AppError> {
+    let email = find_own_email(&auth_info, &state).await?;
+    let id = auth_info.id()?;
+    let account = super::try_find_account(&state, &email)
+        .await?
+        .ok_or(anyhow::anyhow!("Your account {} has been lost!", email))?;
+    let roles = account
+        .find_related(RoleGrant)
+        .all(&state.sql_db)
+        .await?
+        .into_iter()
+        .map(|grant: role_grant::Model| RoleExport {
+            role: grant.role.to_value(),
+            scope_kind: grant.scope_kind.to_value(),
+            scope_id: grant.scope_id,
+            granted_at: grant.granted_at,
+        })
+        .collect();
+    let api_tokens = ApiToken::find()
+        .filter(api_token::Column::Email.eq(email.to_string()))
+        .all(&state.sql_db)
+        .await?
+        .into_iter()
+        .map(|token| ApiTokenExport {
+            name: token.name,
+            scopes: token.scopes,
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            revoked_at: token.revoked_at,
+        })
+        .collect();
+    let profile = profile::try_find_profile_by_id(&state, id)
+        .await?
+        .ok_or(anyhow::anyhow!("Your profile {} has been lost!", id))?;
+    let theses = collect::<Thesis>(
+        &state,
+        doc! {
+            "$or": [
+                { field!(owner_id in ThesisId): id },
+                { field!(author_ids in Thesis): id }
+            ]
+        },
+    )
+    .await?;
+    let versions = collect::<Version>(
+        &state,
+        doc! {
+            field!(uploader_id in Version): id
+        },
+    )
+    .await?;
+    let reviews = collect::<Review>(
+        &state,
+        doc! {
+            field!(reviewer_id in Review): id
+        },
+    )
+    .await?;
+    let comments = collect::<Comment>(
+        &state,
+        doc! {
+            field!(poster_id in Comment): id
+        },
+    )
+    .await?;
+    let notifications = collect::<Notification>(
+        &state,
+        doc! {
+            field!(recipient_id in Notification): id
+        },
+    )
+    .await?;
+    let invitations = collect::<Invitation>(
+        &state,
+        doc! {
+            field!(email in Invitation): to_bson(&email)?
+        },
+    )
+    .await?;
+
+    let entries = vec![
+        json(
+            "account.json",
+            &AccountExport {
+                email: account.email,
+                is_verified: account.is_verified,
+                is_active: account.is_active,
+                totp_enabled: account.totp_enabled,
+                created_at: account.created_at,
+                updated_at: account.updated_at,
+                roles,
+                api_tokens,
+            },
+        )?,
+        json("profile.json", &profile)?,
+        json("theses.json", &theses)?,
+        json("versions.json", &versions)?,
+        json("reviews.json", &reviews)?,
+        json("comments.json", &comments)?,
+        json("notifications.json", &notifications)?,
+        json("invitations.json", &invitations)?,
+    ];
+    let file_ids: Vec<ObjectId> = profile
+        .public_profile
+        .id
+        .avatar_id
+        .into_iter()
+        .chain(
+            versions
+                .iter()
+                .flat_map(|version| std::iter::once(version.file_id).chain(version.source_id)),
+        )
+        .collect();
+
+    let handle = Handle::current();
+    let bucket = state.mongo_db.gridfs_bucket(None);
+    let res = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, AppError> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            zip.start_file(name, zip::write::FileOptions::default())?;
+            zip.write_all(&content)?;
+        }
+        for file_id in file_ids {
+            copy_file(&handle, &bucket, &mut zip, file_id)?;
+        }
+        Ok(zip.finish()?.into_inner())
+    })
+    .await??;
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/zip"),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&format!(
+                    "{}{}{}",
+                    crate::routes::common::DISPOSITION_PREFIX,
+                    ARCHIVE_NAME,
+                    crate::routes::common::DISPOSITION_SUFFIX
+                ))?,
+            ),
+        ],
+        res,
+    ))
+}
+
+async fn delete_file(state: &AppState, id: ObjectId) -> Result<(), AppError> {
+    let bucket = state.mongo_db.gridfs_bucket(None);
+    if bucket
+        .find(
+            doc! {
+                "_id": id
+            },
+            None,
+        )
+        .await?
+        .next()
+        .await
+        .is_some()
+    {
+        bucket.delete(bson!(id)).await?;
+    }
+    Ok(())
+}
+
+/// Hands theses over to a remaining author, or withdraws them when nobody is left.
+async fn release_theses(state: &AppState, id: ObjectId) -> Result<(), AppError> {
+    for thesis in collect::<Thesis>(
+        state,
+        doc! {
+            field!(owner_id in ThesisId): id
+        },
+    )
+    .await?
+    {
+        match thesis.author_ids.iter().find(|author_id| **author_id != id) {
+            Some(heir) => {
+                state
+                    .mongo_db
+                    .repository::<Thesis>()
+                    .update_one(
+                        doc! {
+                            "_id": thesis.id._id
+                        },
+                        doc! {
+                            Set: {
+                                field!(owner_id in ThesisId): heir,
+                                field!(modified_at in ThesisId): DateTime::now()
+                            }
+                        },
+                        None,
+                    )
+                    .await?;
+            }
+            None => {
+                for version in collect::<Version>(
+                    state,
+                    doc! {
+                        field!(thesis_id in Version): thesis.id._id
+                    },
+                )
+                .await?
+                {
+                    delete_file(state, version.file_id).await?;
+                    if let Some(source_id) = version.source_id {
+                        delete_file(state, source_id).await?;
+                    }
+                }
+                thesis.withdraw_all(state.mongo_db.clone()).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Leaves the contributions in place but forgets who made them.
+async fn anonymise(state: &AppState, id: ObjectId) -> Result<(), AppError> {
+    let db = &state.mongo_db;
+    db.repository::<Comment>()
+        .update_many(
+            doc! {
+                field!(poster_id in Comment): id
+            },
+            doc! {
+                Set: {
+                    field!(poster_id in Comment): Bson::Null
+                }
+            },
+            None,
+        )
+        .await?;
+    db.repository::<Review>()
+        .update_many(
+            doc! {
+                field!(reviewer_id in Review): id
+            },
+            doc! {
+                Set: {
+                    field!(reviewer_id in Review): Bson::Null
+                }
+            },
+            None,
+        )
+        .await?;
+    db.repository::<Version>()
+        .update_many(
+            doc! {
+                field!(uploader_id in Version): id
+            },
+            doc! {
+                Set: {
+                    field!(uploader_id in Version): Bson::Null
+                }
+            },
+            None,
+        )
+        .await?;
+    db.repository::<Version>()
+        .update_many(
+            doc! {
+                field!((review_state in Version).(remainder_reviewer_ids in ReviewState)): id
+            },
+            doc! {
+                Pull: {
+                    field!((review_state in Version).(remainder_reviewer_ids in ReviewState)): id
+                }
+            },
+            None,
+        )
+        .await?;
+    db.repository::<Thesis>()
+        .update_many(
+            doc! {
+                field!(author_ids in Thesis): id
+            },
+            doc! {
+                Pull: {
+                    field!(author_ids in Thesis): id
+                },
+                Set: {
+                    field!(modified_at in ThesisId): DateTime::now()
+                }
+            },
+            None,
+        )
+        .await?;
+    db.repository::<Notification>()
+        .delete_many(
+            doc! {
+                field!(recipient_id in Notification): id
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct EraseBody {
+    password: String,
+    #[serde(default)]
+    code: Option<String>,
+}
+
+#[debug_handler]
+async fn erase(
+    ClientIp(ip): ClientIp,
+    auth_info: AuthInfo,
+    mut auth_info_storage: AuthInfoStorage,
+    State(state): State<AppState>,
+    Json(body): Json<EraseBody>,
+) -> Result<StatusCode, AppError> {
+    let email = find_own_email(&auth_info, &state).await?;
+    let id = auth_info.id()?;
+    let account = auth(
+        &state,
+        ip,
+        &AuthBody {
+            email: email.clone(),
+            password: body.password,
+        },
+    )
+    .await?;
+    if account.totp_enabled {
+        totp::check_code(
+            &state,
+            ip,
+            account.clone(),
+            body.code.as_deref().unwrap_or_default(),
+        )
+        .await?;
+    }
+    // Held until the account and its grants are gone, so that no other revocation interleaves.
+    let txn = state.sql_db.begin().await?;
+    role::keep_last_administrator(&txn, &account.find_related(RoleGrant).all(&txn).await?).await?;
+
+    release_theses(&state, id).await?;
+    anonymise(&state, id).await?;
+    let profile = profile::try_find_profile_by_id(&state, id).await?;
+    if let Some(avatar_id) = profile.and_then(|profile| profile.public_profile.id.avatar_id) {
+        avatar::remove(&state, avatar_id).await?;
+    }
+    state
+        .mongo_db
+        .repository::<Profile>()
+        .delete_one(
+            doc! {
+                field!(email in ProfileId): to_bson(&email)?
+            },
+            None,
+        )
+        .await?;
+    account.delete(&txn).await?;
+    txn.commit().await?;
+    auth_info_storage.destroy();
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/profile", routing::delete(erase))
+        .route("/profile/export", routing::get(export))
+}
@@ -1,4 +1,4 @@
-use axum::extract::{Path, Query, State};
+use axum::extract::{OriginalUri, Path, Query, State};
 use axum::http::{HeaderName, HeaderValue};
 use axum::{debug_handler, routing, Json, Router};
 use futures::TryStreamExt;
@@ -7,12 +7,13 @@ use mongodm::bson::Document;
 use mongodm::prelude::{
     MongoFindOneAndReplaceOptions, MongoFindOptions, MongoReturnDocument, ObjectId,
 };
-use mongodm::{doc, ToRepository};
+use mongodm::{doc, field, ToRepository};
+use serde::Deserialize;
 
-use crate::mongo_entities::profile::{Profile, PublicProfile};
+use crate::mongo_entities::profile::{Profile, ProfileId, PublicProfile};
 use crate::routes::common::auth::AuthInfo;
 use crate::routes::common::err::AppError;
-use crate::routes::common::query::AppQuery;
+use crate::routes::common::query::{self, AppQuery, KeysetQuery, Links, Order, Page};
 use crate::state::AppState;
 
 #[debug_handler]
@@ -56,7 +57,7 @@ async fn change(
     Ok(Json(res))
 }
 
-async fn try_find_profile_by_id(
+pub(super) async fn try_find_profile_by_id(
     state: &AppState,
     id: ObjectId,
 ) -> Result<Option<Profile>, AppError> {
@@ -73,6 +74,19 @@ async fn try_find_profile_by_id(
     Ok(res)
 }
 
+pub(super) async fn find_email(
+    state: &AppState,
+    id: ObjectId,
+) -> Result<lettre::Address, AppError> {
+    let res = try_find_profile_by_id(state, id)
+        .await?
+        .ok_or(anyhow::anyhow!("Your profile {} has been lost!", id))?
+        .public_profile
+        .id
+        .email;
+    Ok(res)
+}
+
 #[debug_handler]
 async fn get(
     _auth_info: AuthInfo,
@@ -89,26 +103,87 @@ async fn get(
     Ok(Json(res))
 }
 
+#[derive(Deserialize)]
+#[derive(Default)]
+#[serde(rename_all = "snake_case")]
+enum ProfileSort {
+    #[default]
+    JoiningAt,
+    Name,
+}
+
+/// Only public fields can be filtered on; `ids` is comma-separated.
+#[derive(Deserialize)]
+struct ProfileQuery {
+    #[serde(default)]
+    ids: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    joined_since: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    joined_until: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    sort: ProfileSort,
+    #[serde(default)]
+    order: Order,
+}
+
+impl ProfileQuery {
+    fn filter(&self) -> Result<Document, AppError> {
+        let mut res = Document::new();
+        if let Some(ids) = &self.ids {
+            res.insert("_id", query::one_of::<ObjectId>("ids", ids)?);
+        }
+        if let Some(name) = &self.name {
+            res.insert(field!(name in PublicProfile), name);
+        }
+        if let Some(joining_at) = query::between(self.joined_since, self.joined_until)? {
+            res.insert(field!(joining_at in ProfileId), joining_at);
+        }
+        Ok(res)
+    }
+
+    fn sort_key(&self) -> &'static str {
+        match self.sort {
+            ProfileSort::JoiningAt => field!(joining_at in ProfileId),
+            ProfileSort::Name => field!(name in PublicProfile),
+        }
+    }
+}
+
 #[debug_handler]
 async fn gets(
     _auth_info: AuthInfo,
     State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
     Query(query): Query<AppQuery>,
-    Json(body): Json<Document>,
-) -> Result<([(HeaderName, HeaderValue); 4], Json<Vec<PublicProfile>>), AppError> {
+    Query(keyset): Query<KeysetQuery>,
+    Query(profile_query): Query<ProfileQuery>,
+) -> Result<
+    (
+        [(HeaderName, HeaderValue); 4],
+        Links,
+        Json<Vec<PublicProfile>>,
+    ),
+    AppError,
+> {
+    let filter = profile_query.filter()?;
+    let page = Page::new(&keyset, profile_query.sort_key(), profile_query.order)?;
     let count = state
         .mongo_db
         .repository::<Profile>()
-        .count_documents(body.clone(), None)
+        .count_documents(filter.clone(), None)
         .await?;
     let mut cur = state
         .mongo_db
         .repository::<Profile>()
         .find(
-            body,
+            page.filter(filter),
             MongoFindOptions::builder()
-                .skip(query.offset)
-                .limit(query.limit)
+                .sort(page.sort())
+                .skip(page.skip(&query))
+                .limit(page.limit(&query))
                 .build(),
         )
         .await?;
@@ -116,7 +191,8 @@ async fn gets(
     while let Some(profile) = cur.try_next().await? {
         res.push(profile.public_profile)
     }
-    Ok((query.pagenate(count), Json(res)))
+    let links = page.finish(&query, &uri, &mut res)?;
+    Ok((query.pagenate(count), links, Json(res)))
 }
 
 pub(super) fn new() -> Router<AppState> {
@@ -0,0 +1,270 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::{debug_handler, routing, Json, Router};
+use mongodm::bson::to_bson;
+use mongodm::prelude::ObjectId;
+use sea_orm::prelude::Uuid;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ActiveEnum, ActiveValue, ColumnTrait, Condition, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::mongo_entities::audit::AuditAction;
+use crate::routes::common::audit;
+use crate::routes::common::auth::{AuthInfo, Permission, Scope};
+use crate::routes::common::err::AppError;
+use crate::sql_entities::{
+    prelude::RoleGrant,
+    role_grant::{self, ActiveModel},
+    sea_orm_active_enums::{Role, ScopeKind},
+};
+use crate::state::AppState;
+
+use super::try_find_account;
+
+#[derive(Serialize)]
+pub(super) struct GrantInfo {
+    id: Uuid,
+    email: String,
+    role: String,
+    scope: Scope,
+    granted_at: chrono::NaiveDateTime,
+}
+
+impl TryFrom<role_grant::Model> for GrantInfo {
+    type Error = AppError;
+
+    fn try_from(value: role_grant::Model) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id,
+            scope: Scope::from_columns(&value.scope_kind, value.scope_id.as_deref())?,
+            email: value.email,
+            role: value.role.to_value(),
+            granted_at: value.granted_at,
+        })
+    }
+}
+
+fn parse_role(role: &str) -> Result<Role, AppError> {
+    Role::try_from_value(&role.to_string())
+        .map_err(|_| AppError::BadRequest(format!("No such role {}!", role)))
+}
+
+fn matching(email: &lettre::Address, role: Role, scope: Scope) -> Condition {
+    let (kind, id) = scope.into_columns();
+    Condition::all()
+        .add(role_grant::Column::Email.eq(email.to_string()))
+        .add(role_grant::Column::Role.eq(role))
+        .add(role_grant::Column::ScopeKind.eq(kind))
+        .add(match id {
+            Some(id) => role_grant::Column::ScopeId.eq(id),
+            None => role_grant::Column::ScopeId.is_null(),
+        })
+}
+
+/// Grants `role` within `scope` unless it has been granted already.
+pub(super) async fn grant(
+    state: &AppState,
+    email: &lettre::Address,
+    role: Role,
+    scope: Scope,
+) -> Result<role_grant::Model, AppError> {
+    let (kind, id) = scope.into_columns();
+    // The unique index settles concurrent grants of the same role.
+    RoleGrant::insert(ActiveModel {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        email: ActiveValue::Set(email.to_string()),
+        role: ActiveValue::Set(role.clone()),
+        scope_kind: ActiveValue::Set(kind),
+        scope_id: ActiveValue::Set(id),
+        granted_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+    })
+    .on_conflict(OnConflict::new().do_nothing().to_owned())
+    .exec_without_returning(&state.sql_db)
+    .await?;
+    let res = RoleGrant::find()
+        .filter(matching(email, role, scope))
+        .one(&state.sql_db)
+        .await?
+        .ok_or(anyhow::anyhow!("Cannot find granted role!"))?;
+    Ok(res)
+}
+
+/// Nobody could appoint administrators any more once the last global one is gone. Locks the
+/// global administrator grants, so `db` should be the transaction that removes `grants`.
+pub(super) async fn keep_last_administrator(
+    db: &impl ConnectionTrait,
+    grants: &[role_grant::Model],
+) -> Result<(), AppError> {
+    let revoked = grants
+        .iter()
+        .filter(|grant| grant.role == Role::Administrator && grant.scope_kind == ScopeKind::Global)
+        .count();
+    if revoked > 0
+        && RoleGrant::find()
+            .filter(role_grant::Column::Role.eq(Role::Administrator))
+            .filter(role_grant::Column::ScopeKind.eq(ScopeKind::Global))
+            .lock_exclusive()
+            .all(db)
+            .await?
+            .len()
+            <= revoked
+    {
+        return Err(AppError::Conflict(
+            "The last administrator cannot be removed!".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the grants that have been removed.
+pub(super) async fn revoke(
+    state: &AppState,
+    email: &lettre::Address,
+    role: Role,
+    scope: Scope,
+) -> Result<Vec<role_grant::Model>, AppError> {
+    let condition = matching(email, role, scope);
+    let txn = state.sql_db.begin().await?;
+    let res = RoleGrant::find()
+        .filter(condition.clone())
+        .all(&txn)
+        .await?;
+    keep_last_administrator(&txn, &res).await?;
+    RoleGrant::delete_many()
+        .filter(condition)
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+/// Records grants made or removed by `actor_id` in the audit trail.
+pub(super) async fn audit_grants(
+    state: &AppState,
+    actor_id: ObjectId,
+    action: AuditAction,
+    grants: Vec<role_grant::Model>,
+) -> Result<(), AppError> {
+    for grant in grants {
+        let id = grant.id;
+        let snapshot = Some(to_bson(&GrantInfo::try_from(grant)?)?);
+        let (before, after) = match action {
+            AuditAction::RoleRevoked => (snapshot, None),
+            _ => (None, snapshot),
+        };
+        audit::record(state, actor_id, action, id, before, after).await?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct GrantsQuery {
+    #[serde(default)]
+    email: Option<lettre::Address>,
+    #[serde(default)]
+    role: Option<String>,
+}
+
+#[debug_handler]
+async fn gets(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Query(query): Query<GrantsQuery>,
+) -> Result<Json<Vec<GrantInfo>>, AppError> {
+    if !auth_info.permitted(Permission::Auditing, Scope::Global) {
+        return Err(AppError::Forbidden(
+            "You are not allowed to see roles!".to_string(),
+        ));
+    }
+    let mut select = RoleGrant::find().order_by_asc(role_grant::Column::GrantedAt);
+    if let Some(email) = query.email {
+        select = select.filter(role_grant::Column::Email.eq(email.to_string()));
+    }
+    if let Some(role) = query.role {
+        select = select.filter(role_grant::Column::Role.eq(parse_role(&role)?));
+    }
+    let res = select
+        .all(&state.sql_db)
+        .await?
+        .into_iter()
+        .map(GrantInfo::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok(Json(res))
+}
+
+#[derive(Deserialize)]
+struct GrantBody {
+    email: lettre::Address,
+    role: String,
+    scope: Scope,
+}
+
+#[debug_handler]
+async fn post(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Json(body): Json<GrantBody>,
+) -> Result<(StatusCode, Json<GrantInfo>), AppError> {
+    if !auth_info.permitted(Permission::Managing, body.scope) {
+        return Err(AppError::Forbidden(
+            "You are not an administrator of this scope!".to_string(),
+        ));
+    }
+    if try_find_account(&state, &body.email).await?.is_none() {
+        return Err(AppError::NotFound(format!(
+            "Account with email {} does not exist!",
+            body.email
+        )));
+    }
+    let res = grant(&state, &body.email, parse_role(&body.role)?, body.scope).await?;
+    audit_grants(
+        &state,
+        auth_info.id()?,
+        AuditAction::RoleGranted,
+        vec![res.clone()],
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(res.try_into()?)))
+}
+
+#[debug_handler]
+async fn delete(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let grant = RoleGrant::find_by_id(id)
+        .one(&state.sql_db)
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "Role grant with id {} does not exist!",
+            id
+        )))?;
+    let scope = Scope::from_columns(&grant.scope_kind, grant.scope_id.as_deref())?;
+    if !auth_info.permitted(Permission::Managing, scope) {
+        return Err(AppError::Forbidden(
+            "You are not an administrator of this scope!".to_string(),
+        ));
+    }
+    let txn = state.sql_db.begin().await?;
+    keep_last_administrator(&txn, std::slice::from_ref(&grant)).await?;
+    RoleGrant::delete_by_id(id).exec(&txn).await?;
+    txn.commit().await?;
+    audit_grants(
+        &state,
+        auth_info.id()?,
+        AuditAction::RoleRevoked,
+        vec![grant],
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/roles", routing::get(gets).post(post))
+        .route("/roles/:id", routing::delete(delete))
+}
@@ -0,0 +1,200 @@
+use std::net::IpAddr;
+
+use axum::extract::{Query, State};
+use axum::http::{HeaderName, HeaderValue};
+use axum::{debug_handler, routing, Json, Router};
+use futures_util::TryStreamExt;
+use mongodm::bson::DateTime;
+use mongodm::prelude::{
+    MongoFindOneAndUpdateOptions, MongoFindOptions, MongoReturnDocument, ObjectId,
+};
+use mongodm::{doc, field, ToRepository};
+
+use crate::mongo_entities::throttle::{Lockout, Throttle};
+use crate::routes::common::auth::{AuthInfo, Permission, Scope};
+use crate::routes::common::err::AppError;
+use crate::routes::common::query::AppQuery;
+use crate::state::AppState;
+
+/// The first lockout lasts this long and every further failure doubles it.
+const LOCKOUT_MIN: i64 = 30;
+
+fn subjects(email: &lettre::Address, ip: IpAddr) -> [String; 2] {
+    [format!("account:{}", email), format!("ip:{}", ip)]
+}
+
+async fn find(state: &AppState, subject: &str) -> Result<Option<Throttle>, AppError> {
+    let res = state
+        .mongo_db
+        .repository::<Throttle>()
+        .find_one(
+            doc! {
+                "_id": subject
+            },
+            None,
+        )
+        .await?;
+    Ok(res)
+}
+
+/// Refuses to even look at the credentials while the account or the address is locked.
+pub(super) async fn check(
+    state: &AppState,
+    email: &lettre::Address,
+    ip: IpAddr,
+) -> Result<(), AppError> {
+    let now = chrono::Utc::now();
+    for subject in subjects(email, ip) {
+        if let Some(locked_until) = find(state, &subject)
+            .await?
+            .and_then(|throttle| throttle.locked_until)
+            .map(DateTime::to_chrono)
+            .filter(|locked_until| *locked_until > now)
+        {
+            return Err(AppError::TooManyRequests(format!(
+                "Too many failed attempts, retry in {} seconds!",
+                (locked_until - now).num_seconds() + 1
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub(super) async fn fail(
+    state: &AppState,
+    email: &lettre::Address,
+    ip: IpAddr,
+) -> Result<(), AppError> {
+    let now = chrono::Utc::now();
+    let repo = state.mongo_db.repository::<Throttle>();
+    for (subject, limit) in subjects(email, ip)
+        .into_iter()
+        .zip([state.login_failure_limit, state.ip_failure_limit])
+    {
+        // Failures long forgotten do not count towards a new lockout.
+        repo.update_one(
+            doc! {
+                "_id": &subject,
+                field!(last_failed_at in Throttle): {
+                    "$lt": DateTime::from_chrono(now - state.lockout_max)
+                }
+            },
+            doc! {
+                "$set": {
+                    field!(failures in Throttle): 0
+                }
+            },
+            None,
+        )
+        .await?;
+        // Concurrent failures each count, so the lockout follows from what the increment returns.
+        let failures = repo
+            .find_one_and_update(
+                doc! {
+                    "_id": &subject
+                },
+                doc! {
+                    "$inc": {
+                        field!(failures in Throttle): 1
+                    },
+                    "$set": {
+                        field!(last_failed_at in Throttle): DateTime::from_chrono(now)
+                    }
+                },
+                MongoFindOneAndUpdateOptions::builder()
+                    .upsert(true)
+                    .return_document(MongoReturnDocument::After)
+                    .build(),
+            )
+            .await?
+            .ok_or(anyhow::anyhow!("Cannot get upserted throttle!"))?
+            .failures;
+        if failures < limit {
+            continue;
+        }
+        let delay =
+            chrono::Duration::seconds(LOCKOUT_MIN.saturating_mul(1 << (failures - limit).min(32)))
+                .min(state.lockout_max);
+        state
+            .mongo_db
+            .repository::<Lockout>()
+            .insert_one(
+                Lockout {
+                    _id: ObjectId::new(),
+                    subject: subject.clone(),
+                    failures,
+                    locked_at: now,
+                    locked_until: now + delay,
+                },
+                None,
+            )
+            .await?;
+        repo.update_one(
+            doc! {
+                "_id": &subject
+            },
+            doc! {
+                "$max": {
+                    field!(locked_until in Throttle): DateTime::from_chrono(now + delay)
+                }
+            },
+            None,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Forgets the failures of an account once its owner proves themselves.
+pub(super) async fn clear(state: &AppState, email: &lettre::Address) -> Result<(), AppError> {
+    state
+        .mongo_db
+        .repository::<Throttle>()
+        .delete_one(
+            doc! {
+                "_id": format!("account:{}", email)
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+#[debug_handler]
+async fn lockouts(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Query(query): Query<AppQuery>,
+) -> Result<([(HeaderName, HeaderValue); 4], Json<Vec<Lockout>>), AppError> {
+    if !auth_info.permitted(Permission::Managing, Scope::Global) {
+        return Err(AppError::Forbidden(
+            "You are not an administrator!".to_string(),
+        ));
+    }
+    let count = state
+        .mongo_db
+        .repository::<Lockout>()
+        .count_documents(doc! {}, None)
+        .await?;
+    let res = state
+        .mongo_db
+        .repository::<Lockout>()
+        .find(
+            doc! {},
+            MongoFindOptions::builder()
+                .sort(doc! {
+                    "_id": -1
+                })
+                .skip(query.offset)
+                .limit(query.limit)
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok((query.pagenate(count), Json(res)))
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new().route("/lockouts", routing::get(lockouts))
+}
@@ -0,0 +1,82 @@
+use sea_orm::{ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
+
+use crate::routes::common::{err::AppError, secret};
+use crate::sql_entities::{
+    account_token::{self, ActiveModel},
+    prelude::AccountToken,
+    sea_orm_active_enums::TokenPurpose,
+};
+use crate::state::AppState;
+
+/// Issues a one-time token for `email`; only its digest is kept in the database.
+pub(super) async fn issue(
+    state: &AppState,
+    email: &lettre::Address,
+    purpose: TokenPurpose,
+    ttl: chrono::Duration,
+) -> Result<String, AppError> {
+    let token = secret::generate();
+    let now = chrono::Utc::now().naive_utc();
+    AccountToken::insert(ActiveModel {
+        token_hash: ActiveValue::Set(secret::digest(&token)),
+        email: ActiveValue::Set(email.to_string()),
+        purpose: ActiveValue::Set(purpose),
+        created_at: ActiveValue::Set(now),
+        expires_at: ActiveValue::Set(now + ttl),
+    })
+    .exec(&state.sql_db)
+    .await?;
+    Ok(token)
+}
+
+/// Consumes `token` and returns the email it was issued for.
+pub(super) async fn redeem(
+    state: &AppState,
+    token: &str,
+    purpose: TokenPurpose,
+) -> Result<lettre::Address, AppError> {
+    let invalid = || AppError::BadRequest("Invalid or expired token!".to_string());
+    let token_hash = secret::digest(token);
+    let model = AccountToken::find_by_id(token_hash.clone())
+        .one(&state.sql_db)
+        .await?
+        .ok_or_else(invalid)?;
+    // Only the request whose delete actually removes the row may use the token.
+    let res = AccountToken::delete_many()
+        .filter(account_token::Column::TokenHash.eq(token_hash))
+        .filter(account_token::Column::Purpose.eq(purpose))
+        .filter(account_token::Column::ExpiresAt.gt(chrono::Utc::now().naive_utc()))
+        .exec(&state.sql_db)
+        .await?;
+    if res.rows_affected != 1 {
+        return Err(invalid());
+    }
+    Ok(model.email.parse()?)
+}
+
+pub(super) async fn revoke_all(
+    state: &AppState,
+    email: &lettre::Address,
+    purpose: TokenPurpose,
+) -> Result<(), AppError> {
+    AccountToken::delete_many()
+        .filter(account_token::Column::Email.eq(email.to_string()))
+        .filter(account_token::Column::Purpose.eq(purpose))
+        .exec(&state.sql_db)
+        .await?;
+    Ok(())
+}
+
+pub(super) async fn is_pending(
+    state: &AppState,
+    email: &lettre::Address,
+    purpose: TokenPurpose,
+) -> Result<bool, AppError> {
+    let res = AccountToken::find()
+        .filter(account_token::Column::Email.eq(email.to_string()))
+        .filter(account_token::Column::Purpose.eq(purpose))
+        .filter(account_token::Column::ExpiresAt.gt(chrono::Utc::now().naive_utc()))
+        .one(&state.sql_db)
+        .await?;
+    Ok(res.is_some())
+}
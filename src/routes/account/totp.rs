@@ -0,0 +1,287 @@
+use std::net::IpAddr;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::{debug_handler, routing, Json, Router};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::mongo_entities::profile::Profile;
+use crate::routes::common::auth::{AuthInfo, AuthInfoStorage};
+use crate::routes::common::client_ip::ClientIp;
+use crate::routes::common::{err::AppError, secret};
+use crate::sql_entities::{
+    account::{self, ActiveModel},
+    prelude::{Account, RecoveryCode},
+    recovery_code,
+};
+use crate::state::AppState;
+
+use super::{auth, find_own_email, finish_login, throttle, try_find_account, AuthBody};
+
+const STEP: u64 = 30;
+const RECOVERY_CODES: usize = 10;
+const PENDING_LOGIN: &str = "pending_login";
+const PENDING_LOGIN_TTL: i64 = 5 * 60;
+
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    email: lettre::Address,
+    expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+fn totp(state: &AppState, secret: Vec<u8>, email: &str) -> Result<TOTP, AppError> {
+    let res = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        STEP,
+        secret,
+        Some(state.totp_issuer.to_string()),
+        email.to_string(),
+    )?;
+    Ok(res)
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Accepts a code from the enrolled authenticator or else a recovery code, either only once.
+pub(super) async fn check_code(
+    state: &AppState,
+    ip: IpAddr,
+    account: account::Model,
+    code: &str,
+) -> Result<(), AppError> {
+    let email = account.email.parse()?;
+    throttle::check(state, &email, ip).await?;
+    if consume_code(state, account, code).await? {
+        throttle::clear(state, &email).await
+    } else {
+        throttle::fail(state, &email, ip).await?;
+        Err(AppError::Forbidden("Wrong verification code!".to_string()))
+    }
+}
+
+async fn consume_code(
+    state: &AppState,
+    account: account::Model,
+    code: &str,
+) -> Result<bool, AppError> {
+    let code = code.trim();
+    if let Some(secret) = account.totp_secret.clone() {
+        let totp = totp(state, secret, &account.email)?;
+        let current = u64::try_from(chrono::Utc::now().timestamp())? / STEP;
+        if let Some(step) =
+            (current - 1..=current + 1).find(|step| totp.generate(step * STEP) == code)
+        {
+            let step = i64::try_from(step)?;
+            // A code observed once must not be replayed within its window; only the request
+            // whose update actually advances the step may use it.
+            let res = Account::update_many()
+                .col_expr(account::Column::TotpStep, Expr::value(step))
+                .filter(account::Column::Email.eq(account.email))
+                .filter(
+                    Condition::any()
+                        .add(account::Column::TotpStep.is_null())
+                        .add(account::Column::TotpStep.lt(step)),
+                )
+                .exec(&state.sql_db)
+                .await?;
+            return Ok(res.rows_affected == 1);
+        }
+    }
+    let res = RecoveryCode::delete_many()
+        .filter(recovery_code::Column::Email.eq(account.email))
+        .filter(recovery_code::Column::CodeHash.eq(secret::digest(&normalize_recovery_code(code))))
+        .exec(&state.sql_db)
+        .await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// Remembers a password-authenticated login until its second step arrives.
+pub(super) fn defer_login(
+    auth_info_storage: &mut AuthInfoStorage,
+    email: lettre::Address,
+) -> Result<(), AppError> {
+    auth_info_storage.insert(
+        PENDING_LOGIN,
+        PendingLogin {
+            email,
+            expires_at: chrono::Utc::now() + chrono::Duration::seconds(PENDING_LOGIN_TTL),
+        },
+    )?;
+    Ok(())
+}
+
+async fn find_own_account(
+    auth_info: &AuthInfo,
+    state: &AppState,
+) -> Result<account::Model, AppError> {
+    let email = find_own_email(auth_info, state).await?;
+    let res = try_find_account(state, &email)
+        .await?
+        .ok_or(anyhow::anyhow!("Your account {} has been lost!", email))?;
+    Ok(res)
+}
+
+#[derive(Serialize)]
+struct Enrolment {
+    secret: String,
+    url: String,
+}
+
+#[debug_handler]
+async fn enrol(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<Enrolment>), AppError> {
+    let account = find_own_account(&auth_info, &state).await?;
+    if account.totp_enabled {
+        return Err(AppError::Conflict(
+            "TOTP has already been enabled!".to_string(),
+        ));
+    }
+
+    let secret = Secret::generate_secret().to_bytes()?;
+    let totp = totp(&state, secret.clone(), &account.email)?;
+    let mut account: ActiveModel = account.into();
+    account.totp_secret = ActiveValue::Set(Some(secret));
+    account.totp_step = ActiveValue::Set(None);
+    account.update(&state.sql_db).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(Enrolment {
+            secret: totp.get_secret_base32(),
+            url: totp.get_url(),
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+struct CodeBody {
+    code: String,
+}
+
+#[debug_handler]
+async fn confirm(
+    ClientIp(ip): ClientIp,
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Json(body): Json<CodeBody>,
+) -> Result<Json<Vec<String>>, AppError> {
+    let account = find_own_account(&auth_info, &state).await?;
+    if account.totp_enabled {
+        return Err(AppError::Conflict(
+            "TOTP has already been enabled!".to_string(),
+        ));
+    }
+    if account.totp_secret.is_none() {
+        return Err(AppError::BadRequest(
+            "TOTP enrolment has not been started!".to_string(),
+        ));
+    }
+    check_code(&state, ip, account.clone(), &body.code).await?;
+
+    let email = account.email.clone();
+    let mut account: ActiveModel = account.into();
+    account.totp_enabled = ActiveValue::Set(true);
+    account.updated_at = ActiveValue::Set(chrono::Utc::now().naive_utc());
+    account.update(&state.sql_db).await?;
+
+    RecoveryCode::delete_many()
+        .filter(recovery_code::Column::Email.eq(email.clone()))
+        .exec(&state.sql_db)
+        .await?;
+    let codes = (0..RECOVERY_CODES)
+        .map(|_| secret::generate()[..16].to_string())
+        .collect::<Vec<_>>();
+    RecoveryCode::insert_many(codes.iter().map(|code| recovery_code::ActiveModel {
+        code_hash: ActiveValue::Set(secret::digest(code)),
+        email: ActiveValue::Set(email.clone()),
+        created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+    }))
+    .exec(&state.sql_db)
+    .await?;
+    Ok(Json(codes))
+}
+
+#[derive(Deserialize)]
+struct DisableBody {
+    password: String,
+    code: String,
+}
+
+#[debug_handler]
+async fn disable(
+    ClientIp(ip): ClientIp,
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Json(body): Json<DisableBody>,
+) -> Result<(), AppError> {
+    let email = find_own_email(&auth_info, &state).await?;
+    let account = auth(
+        &state,
+        ip,
+        &AuthBody {
+            email: email.clone(),
+            password: body.password,
+        },
+    )
+    .await?;
+    if !account.totp_enabled {
+        return Err(AppError::BadRequest("TOTP is not enabled!".to_string()));
+    }
+    check_code(&state, ip, account.clone(), &body.code).await?;
+
+    let mut account: ActiveModel = account.into();
+    account.totp_secret = ActiveValue::Set(None);
+    account.totp_enabled = ActiveValue::Set(false);
+    account.totp_step = ActiveValue::Set(None);
+    account.updated_at = ActiveValue::Set(chrono::Utc::now().naive_utc());
+    account.update(&state.sql_db).await?;
+    RecoveryCode::delete_many()
+        .filter(recovery_code::Column::Email.eq(email.to_string()))
+        .exec(&state.sql_db)
+        .await?;
+    Ok(())
+}
+
+#[debug_handler]
+async fn login(
+    ClientIp(ip): ClientIp,
+    mut auth_info_storage: AuthInfoStorage,
+    State(state): State<AppState>,
+    Json(body): Json<CodeBody>,
+) -> Result<Json<Profile>, AppError> {
+    let pending = auth_info_storage
+        .get::<PendingLogin>(PENDING_LOGIN)
+        .filter(|pending| pending.expires_at > chrono::Utc::now())
+        .ok_or(AppError::Forbidden(
+            "Log in with your password first!".to_string(),
+        ))?;
+    let account = try_find_account(&state, &pending.email)
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "Account with email {} does not exist!",
+            pending.email
+        )))?;
+    check_code(&state, ip, account.clone(), &body.code).await?;
+
+    auth_info_storage.remove(PENDING_LOGIN);
+    let res = finish_login(&state, &mut auth_info_storage, account).await?;
+    Ok(Json(res))
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/totp", routing::post(enrol).delete(disable))
+        .route("/totp/confirm", routing::post(confirm))
+        .route("/login/totp", routing::post(login))
+}
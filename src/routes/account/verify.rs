@@ -0,0 +1,83 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::{debug_handler, routing, Json, Router};
+use sea_orm::{ActiveModelTrait, ActiveValue};
+use serde::Deserialize;
+
+use crate::routes::common::{err::AppError, mail};
+use crate::routes::invitation;
+use crate::sql_entities::{account::ActiveModel, sea_orm_active_enums::TokenPurpose};
+use crate::state::AppState;
+
+use super::{token, try_find_account, try_find_profile};
+
+pub(super) async fn send_verification(
+    state: &AppState,
+    email: &lettre::Address,
+) -> Result<(), AppError> {
+    token::revoke_all(state, email, TokenPurpose::Verify).await?;
+    let token = token::issue(state, email, TokenPurpose::Verify, state.verification_ttl).await?;
+    mail::send(
+        state,
+        email,
+        "Confirm your email address",
+        format!(
+            "Open the following link to confirm your email address:\n\n{}/verify?token={}\n\nThe link expires in {} hours and can be used only once.",
+            state.clt_addr,
+            token,
+            state.verification_ttl.num_hours()
+        ),
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+struct VerifyBody {
+    token: String,
+}
+
+#[debug_handler]
+async fn verify(
+    State(state): State<AppState>,
+    Json(body): Json<VerifyBody>,
+) -> Result<(), AppError> {
+    let email = token::redeem(&state, &body.token, TokenPurpose::Verify).await?;
+    let mut account: ActiveModel = try_find_account(&state, &email)
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "Account with email {} does not exist!",
+            email
+        )))?
+        .into();
+    account.is_verified = ActiveValue::Set(true);
+    account.updated_at = ActiveValue::Set(chrono::Utc::now().naive_utc());
+    account.update(&state.sql_db).await?;
+    if let Some(profile) = try_find_profile(&state, &email).await? {
+        invitation::resolve_all(&state, &email, profile.public_profile.id._id).await?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ResendBody {
+    email: lettre::Address,
+}
+
+#[debug_handler]
+async fn resend(
+    State(state): State<AppState>,
+    Json(body): Json<ResendBody>,
+) -> Result<StatusCode, AppError> {
+    if let Some(account) = try_find_account(&state, &body.email).await? {
+        if !account.is_verified {
+            send_verification(&state, &body.email).await?;
+        }
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/verify", routing::post(verify))
+        .route("/verify/resend", routing::post(resend))
+}
@@ -0,0 +1,88 @@
+use axum::extract::{Query, State};
+use axum::http::{HeaderName, HeaderValue};
+use axum::{debug_handler, routing, Json, Router};
+use futures_util::TryStreamExt;
+use mongodm::bson::{to_bson, Document};
+use mongodm::prelude::{MongoFindOptions, ObjectId};
+use mongodm::{doc, field, ToRepository};
+use serde::Deserialize;
+
+use crate::mongo_entities::audit::{AuditAction, AuditLog};
+use crate::routes::common::auth::{AuthInfo, Permission, Scope};
+use crate::routes::common::err::AppError;
+use crate::routes::common::query::{self, AppQuery};
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    #[serde(default)]
+    actor_id: Option<ObjectId>,
+    #[serde(default)]
+    action: Option<AuditAction>,
+    #[serde(default)]
+    target_id: Option<String>,
+    #[serde(default)]
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl AuditQuery {
+    fn filter(self) -> Result<Document, AppError> {
+        let mut res = Document::new();
+        if let Some(actor_id) = self.actor_id {
+            res.insert(field!(actor_id in AuditLog), actor_id);
+        }
+        if let Some(action) = self.action {
+            res.insert(field!(action in AuditLog), to_bson(&action)?);
+        }
+        if let Some(target_id) = self.target_id {
+            res.insert(field!(target_id in AuditLog), target_id);
+        }
+        if let Some(at) = query::between(self.since, self.until)? {
+            res.insert(field!(at in AuditLog), at);
+        }
+        Ok(res)
+    }
+}
+
+#[debug_handler]
+async fn gets(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Query(query): Query<AppQuery>,
+    Query(audit_query): Query<AuditQuery>,
+) -> Result<([(HeaderName, HeaderValue); 4], Json<Vec<AuditLog>>), AppError> {
+    if auth_info.by_token || !auth_info.permitted(Permission::Managing, Scope::Global) {
+        return Err(AppError::Forbidden(
+            "You are not an administrator!".to_string(),
+        ));
+    }
+    let filter = audit_query.filter()?;
+    let count = state
+        .mongo_db
+        .repository::<AuditLog>()
+        .count_documents(filter.clone(), None)
+        .await?;
+    let res = state
+        .mongo_db
+        .repository::<AuditLog>()
+        .find(
+            filter,
+            MongoFindOptions::builder()
+                .sort(doc! {
+                    field!(at in AuditLog): -1
+                })
+                .skip(query.offset)
+                .limit(query.limit)
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok((query.pagenate(count), Json(res)))
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new().route("/", routing::get(gets))
+}
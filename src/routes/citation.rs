@@ -0,0 +1,474 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, OriginalUri, Path, Query, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use axum::{debug_handler, routing, Router};
+use chrono::Datelike;
+use futures_util::TryStreamExt;
+use mongodm::prelude::{MongoFindOptions, ObjectId};
+use mongodm::{doc, field, ToRepository};
+use serde::Deserialize;
+
+use crate::mongo_entities::paper_collection::Magazine;
+use crate::mongo_entities::profile::Profile;
+use crate::mongo_entities::thesis::{Thesis, Version};
+use crate::routes::common::auth::AuthInfo;
+use crate::routes::common::err::AppError;
+use crate::routes::common::query::{AppQuery, KeysetQuery, Links};
+use crate::routes::thesis::{credited_ids, find_thesis_by_id, list, split_name, ThesisQuery};
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+#[derive(Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+enum CitationFormat {
+    Bibtex,
+    Ris,
+    CslJson,
+}
+
+impl CitationFormat {
+    const ALL: [Self; 3] = [Self::Bibtex, Self::Ris, Self::CslJson];
+
+    fn mime(self) -> &'static str {
+        match self {
+            Self::Bibtex => "application/x-bibtex",
+            Self::Ris => "application/x-research-info-systems",
+            Self::CslJson => "application/vnd.citationstyles.csl+json",
+        }
+    }
+
+    /// The first acceptable media type that is one of ours.
+    fn accepted(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|media_type| media_type.split(';').next())
+            .find_map(|media_type| {
+                Self::ALL
+                    .into_iter()
+                    .find(|format| format.mime().eq_ignore_ascii_case(media_type.trim()))
+            })
+    }
+
+    fn render(self, citations: &[Citation]) -> Result<String, AppError> {
+        let keys = Citation::keys(citations);
+        let res = match self {
+            Self::Bibtex => citations
+                .iter()
+                .zip(&keys)
+                .map(|(citation, key)| citation.to_bibtex(key))
+                .collect::<Result<Vec<_>, _>>()?
+                .join("\n"),
+            Self::Ris => citations
+                .iter()
+                .map(Citation::to_ris)
+                .collect::<Result<Vec<_>, _>>()?
+                .concat(),
+            Self::CslJson => serde_json::to_string_pretty(
+                &citations
+                    .iter()
+                    .zip(&keys)
+                    .map(|(citation, key)| citation.to_csl_json(key))
+                    .collect::<Vec<_>>(),
+            )?,
+        };
+        Ok(res)
+    }
+}
+
+#[derive(Deserialize)]
+struct FormatQuery {
+    #[serde(default)]
+    format: Option<CitationFormat>,
+}
+
+/// `format` wins over the `Accept` header; BibTeX is the fallback.
+struct Negotiated(CitationFormat);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Negotiated
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<FormatQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|err| AppError::BadRequest(err.body_text()))?;
+        Ok(Self(
+            query
+                .format
+                .or_else(|| CitationFormat::accepted(&parts.headers))
+                .unwrap_or(CitationFormat::Bibtex),
+        ))
+    }
+}
+
+/// What every format is rendered from.
+struct Citation {
+    id: ObjectId,
+    title: String,
+    authors: Vec<String>,
+    magazine: Option<String>,
+    issued_at: chrono::DateTime<chrono::Utc>,
+    doi: Option<String>,
+    keywords: Vec<String>,
+    abstraction: String,
+    url: String,
+}
+
+impl Citation {
+    /// Cites `theses` in order, looking up their authors, magazines and publication dates
+    /// in one query each. Fails for theses that have not passed, which are not citable.
+    async fn cite(state: &AppState, theses: Vec<Thesis>) -> Result<Vec<Self>, AppError> {
+        let ids: Vec<ObjectId> = theses.iter().map(|thesis| thesis.id._id).collect();
+        let author_ids: BTreeSet<ObjectId> = theses.iter().flat_map(credited_ids).collect();
+        let magazine_ids: BTreeSet<ObjectId> = theses
+            .iter()
+            .filter_map(|thesis| thesis.magazine_id)
+            .collect();
+        let names: HashMap<ObjectId, String> = state
+            .mongo_db
+            .repository::<Profile>()
+            .find(
+                doc! {
+                    "_id": {
+                        "$in": author_ids.into_iter().collect::<Vec<_>>()
+                    }
+                },
+                None,
+            )
+            .await?
+            .map_ok(|profile| (profile.public_profile.id._id, profile.public_profile.name))
+            .try_collect()
+            .await?;
+        let magazines: HashMap<ObjectId, String> = state
+            .mongo_db
+            .repository::<Magazine>()
+            .find(
+                doc! {
+                    "_id": {
+                        "$in": magazine_ids.into_iter().collect::<Vec<_>>()
+                    }
+                },
+                None,
+            )
+            .await?
+            .map_ok(|magazine| (magazine.meta._id, magazine.meta.name))
+            .try_collect()
+            .await?;
+        // The first version that passed dates each thesis, as in `find_publication_date`;
+        // it comes last and so overwrites the later ones.
+        let mut versions = state
+            .mongo_db
+            .repository::<Version>()
+            .find(
+                doc! {
+                    field!(thesis_id in Version): {
+                        "$in": &ids
+                    },
+                    field!(major_num in Version): {
+                        "$gt": 0
+                    }
+                },
+                MongoFindOptions::builder()
+                    .sort(doc! {
+                        field!(major_num in Version): -1
+                    })
+                    .build(),
+            )
+            .await?;
+        let mut issued_ats = HashMap::with_capacity(ids.len());
+        while let Some(version) = versions.try_next().await? {
+            issued_ats.insert(
+                version.thesis_id,
+                version.passed_at.unwrap_or(version.uploaded_at),
+            );
+        }
+        theses
+            .into_iter()
+            .map(|thesis| {
+                let id = thesis.id._id;
+                // Authors who have erased their accounts are no longer credited.
+                let authors = credited_ids(&thesis)
+                    .iter()
+                    .filter_map(|id| names.get(id).cloned())
+                    .collect();
+                Ok(Self {
+                    id,
+                    issued_at: *issued_ats.get(&id).ok_or(AppError::BadRequest(format!(
+                        "Thesis {} has not passed yet!",
+                        id
+                    )))?,
+                    authors,
+                    magazine: thesis
+                        .magazine_id
+                        .and_then(|magazine_id| magazines.get(&magazine_id).cloned()),
+                    title: thesis.title,
+                    doi: thesis.doi,
+                    keywords: thesis.keywords,
+                    abstraction: thesis.abstraction,
+                    url: format!("{}/theses/{}", state.clt_addr, id),
+                })
+            })
+            .collect()
+    }
+
+    /// First author's surname and the year, e.g. `knuth1984`.
+    fn key(&self) -> String {
+        let surname: String = self
+            .authors
+            .first()
+            .map(|name| split_name(name).1)
+            .unwrap_or_default()
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect();
+        if surname.is_empty() {
+            self.id.to_hex()
+        } else {
+            format!(
+                "{}{}",
+                surname.to_ascii_lowercase(),
+                self.issued_at.format("%Y")
+            )
+        }
+    }
+
+    /// `key`s made unique within one export by suffixing the ones that collide in list
+    /// order, e.g. `knuth1984a` and `knuth1984b`.
+    fn keys(citations: &[Self]) -> Vec<String> {
+        let keys: Vec<String> = citations.iter().map(Self::key).collect();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for key in &keys {
+            *counts.entry(key).or_default() += 1;
+        }
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        keys.iter()
+            .map(|key| {
+                if counts[key.as_str()] == 1 {
+                    return key.clone();
+                }
+                let n = seen.entry(key).or_default();
+                let res = format!("{}{}", key, key_suffix(*n));
+                *n += 1;
+                res
+            })
+            .collect()
+    }
+
+    fn to_bibtex(&self, key: &str) -> Result<String, std::fmt::Error> {
+        let mut res = String::new();
+        writeln!(res, "@article{{{},", key)?;
+        writeln!(res, "  title = {{{}}},", bibtex_escape(&self.title))?;
+        if !self.authors.is_empty() {
+            let authors: Vec<String> = self
+                .authors
+                .iter()
+                .map(|name| bibtex_escape(name))
+                .collect();
+            writeln!(res, "  author = {{{}}},", authors.join(" and "))?;
+        }
+        if let Some(magazine) = &self.magazine {
+            writeln!(res, "  journal = {{{}}},", bibtex_escape(magazine))?;
+        }
+        writeln!(res, "  year = {{{}}},", self.issued_at.format("%Y"))?;
+        writeln!(
+            res,
+            "  month = {{{}}},",
+            self.issued_at.format("%b").to_string().to_lowercase()
+        )?;
+        if let Some(doi) = &self.doi {
+            writeln!(res, "  doi = {{{}}},", bibtex_escape(doi))?;
+        }
+        if !self.keywords.is_empty() {
+            writeln!(
+                res,
+                "  keywords = {{{}}},",
+                bibtex_escape(&self.keywords.join(", "))
+            )?;
+        }
+        writeln!(res, "  url = {{{}}}", self.url)?;
+        writeln!(res, "}}")?;
+        Ok(res)
+    }
+
+    fn to_ris(&self) -> Result<String, std::fmt::Error> {
+        let mut res = String::new();
+        let mut tag = |tag: &str, value: &str| -> std::fmt::Result {
+            write!(res, "{}  - {}\r\n", tag, ris_escape(value))
+        };
+        tag("TY", "JOUR")?;
+        tag("TI", &self.title)?;
+        for name in &self.authors {
+            match split_name(name) {
+                (Some(given_name), surname) => tag("AU", &format!("{}, {}", surname, given_name))?,
+                (None, surname) => tag("AU", surname)?,
+            }
+        }
+        if let Some(magazine) = &self.magazine {
+            tag("T2", magazine)?;
+        }
+        tag("PY", &self.issued_at.format("%Y").to_string())?;
+        tag("DA", &self.issued_at.format("%Y/%m/%d").to_string())?;
+        if let Some(doi) = &self.doi {
+            tag("DO", doi)?;
+        }
+        for keyword in &self.keywords {
+            tag("KW", keyword)?;
+        }
+        if !self.abstraction.is_empty() {
+            tag("AB", &self.abstraction)?;
+        }
+        tag("UR", &self.url)?;
+        tag("ER", "")?;
+        Ok(res)
+    }
+
+    fn to_csl_json(&self, key: &str) -> serde_json::Value {
+        let authors: Vec<serde_json::Value> = self
+            .authors
+            .iter()
+            .map(|name| match split_name(name) {
+                (Some(given_name), surname) => serde_json::json!({
+                    "family": surname,
+                    "given": given_name
+                }),
+                (None, surname) => serde_json::json!({
+                    "literal": surname
+                }),
+            })
+            .collect();
+        let mut res = serde_json::json!({
+            "id": key,
+            "type": "article-journal",
+            "title": self.title,
+            "author": authors,
+            "issued": {
+                "date-parts": [[
+                    self.issued_at.year(),
+                    self.issued_at.month(),
+                    self.issued_at.day()
+                ]]
+            },
+            "URL": self.url
+        });
+        if let Some(magazine) = &self.magazine {
+            res["container-title"] = magazine.as_str().into();
+        }
+        if let Some(doi) = &self.doi {
+            res["DOI"] = doi.as_str().into();
+        }
+        if !self.keywords.is_empty() {
+            res["keyword"] = self.keywords.join(", ").into();
+        }
+        if !self.abstraction.is_empty() {
+            res["abstract"] = self.abstraction.as_str().into();
+        }
+        res
+    }
+}
+
+/// `a` to `z`, then `aa`, `ab` and so on.
+fn key_suffix(mut n: usize) -> String {
+    let mut res = Vec::new();
+    loop {
+        res.push(b'a' + (n % 26) as u8);
+        n /= 26;
+        if n == 0 {
+            break;
+        }
+        n -= 1;
+    }
+    res.reverse();
+    String::from_utf8(res).unwrap_or_default()
+}
+
+fn bibtex_escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => res.push_str("\\textbackslash{}"),
+            '~' => res.push_str("\\textasciitilde{}"),
+            '^' => res.push_str("\\textasciicircum{}"),
+            '{' | '}' | '&' | '%' | '$' | '#' | '_' => {
+                res.push('\\');
+                res.push(c);
+            }
+            '\r' | '\n' => res.push(' '),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Each RIS tag takes a single line.
+fn ris_escape(text: &str) -> String {
+    text.split(['\r', '\n'])
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[debug_handler]
+async fn get(
+    _auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectId>,
+    Negotiated(format): Negotiated,
+) -> Result<([(HeaderName, HeaderValue); 1], String), AppError> {
+    let citations = Citation::cite(&state, vec![find_thesis_by_id(&state, id).await?]).await?;
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.mime()),
+        )],
+        format.render(&citations)?,
+    ))
+}
+
+/// Cites a page of the same list `GET /theses` returns.
+#[debug_handler]
+async fn gets(
+    _auth_info: AuthInfo,
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Negotiated(format): Negotiated,
+    Query(query): Query<AppQuery>,
+    Query(keyset): Query<KeysetQuery>,
+    Query(thesis_query): Query<ThesisQuery>,
+) -> Result<
+    (
+        [(HeaderName, HeaderValue); 4],
+        Links,
+        [(HeaderName, HeaderValue); 1],
+        String,
+    ),
+    AppError,
+> {
+    let (count, links, theses) = list(&state, &uri, &query, &keyset, &thesis_query).await?;
+    let citations = Citation::cite(&state, theses).await?;
+    // Replaces the JSON content type among the pagination headers.
+    Ok((
+        query.pagenate(count),
+        links,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.mime()),
+        )],
+        format.render(&citations)?,
+    ))
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/citations", routing::get(gets))
+        .route("/:id/citation", routing::get(get))
+}
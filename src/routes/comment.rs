@@ -4,9 +4,11 @@ use axum::{debug_handler, routing, Json, Router};
 use mongodm::prelude::ObjectId;
 use mongodm::{doc, ToRepository};
 
+use crate::mongo_entities::notification::NotificationKind;
 use crate::mongo_entities::thesis::{Comment, CommentTargetType};
 use crate::routes::common::auth::AuthInfo;
 use crate::routes::common::err::AppError;
+use crate::routes::common::notify;
 use crate::state::AppState;
 
 #[debug_handler]
@@ -43,13 +45,22 @@ async fn reply(
     Path(id): Path<ObjectId>,
     Json(mut body): Json<Comment>,
 ) -> Result<(StatusCode, Json<ObjectId>), AppError> {
-    find_comment_by_id(&state, id).await?;
+    let parent = find_comment_by_id(&state, id).await?;
 
     body.poster_id = Some(auth_info.id()?);
     body.posted_at = chrono::Utc::now();
     body.target_type = CommentTargetType::Comment;
     body.target_id = id;
     let res = insert_comment(&state, body).await?;
+    notify::notify(
+        &state,
+        auth_info.id()?,
+        parent.poster_id,
+        NotificationKind::ReplyPosted,
+        res,
+        "Someone has replied to your comment.".to_string(),
+    )
+    .await?;
     Ok((StatusCode::CREATED, Json(res)))
 }
 
@@ -0,0 +1,35 @@
+use mongodm::bson::Bson;
+use mongodm::prelude::ObjectId;
+use mongodm::ToRepository;
+
+use crate::mongo_entities::audit::{AuditAction, AuditLog};
+use crate::routes::common::err::AppError;
+use crate::state::AppState;
+
+/// Appends to the audit trail; call it once the change has been made.
+pub(crate) async fn record(
+    state: &AppState,
+    actor_id: ObjectId,
+    action: AuditAction,
+    target_id: impl ToString,
+    before: Option<Bson>,
+    after: Option<Bson>,
+) -> Result<(), AppError> {
+    state
+        .mongo_db
+        .repository::<AuditLog>()
+        .insert_one(
+            AuditLog {
+                _id: ObjectId::new(),
+                actor_id,
+                action,
+                target_id: target_id.to_string(),
+                before,
+                after,
+                at: chrono::Utc::now(),
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
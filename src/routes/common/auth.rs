@@ -1,58 +1,352 @@
+use std::collections::BTreeSet;
+
 use async_trait::async_trait;
-use axum::extract::FromRequestParts;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::headers::authorization::{Authorization, Bearer};
 use axum::http::request::Parts;
+use axum::http::Method;
+use axum::TypedHeader;
 use axum_sessions::{
     async_session::Session,
     extractors::{ReadableSession, WritableSession},
 };
+use futures_util::TryStreamExt;
+use mongodm::bson::to_bson;
 use mongodm::prelude::ObjectId;
+use mongodm::{doc, field, ToRepository};
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
 use serde::{Deserialize, Serialize};
 
-use crate::routes::common::err::AppError;
+use crate::mongo_entities::paper_collection::Category;
+use crate::mongo_entities::profile::{Profile, ProfileId};
+use crate::mongo_entities::thesis::Thesis;
+use crate::routes::common::{err::AppError, secret};
+use crate::sql_entities::{
+    account, api_token,
+    prelude::{Account, ApiToken, RoleGrant},
+    role_grant,
+    sea_orm_active_enums::{Role, ScopeKind},
+};
+use crate::state::AppState;
 
-#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
 #[derive(Copy, Clone)]
 #[derive(Debug)]
-#[repr(usize)]
+#[serde(rename_all = "snake_case")]
 pub(crate) enum Permission {
-    Managing = 0,
-    Publishing = 1,
+    /// Accounts, roles and magazines.
+    Managing,
+    /// Editorial decisions on theses and versions.
+    Publishing,
+    /// Membership of the reviewer pool.
+    Reviewing,
+    /// Reading everything without changing anything.
+    Auditing,
+}
+
+impl Permission {
+    /// Withheld from accounts without a second factor when the deployment demands one.
+    pub(crate) fn is_privileged(&self) -> bool {
+        matches!(self, Self::Managing | Self::Publishing)
+    }
 }
 
+/// The resources a role grant covers; `Global` covers all of them.
 #[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub(crate) enum Scope {
+    Global,
+    Magazine(ObjectId),
+    Category(ObjectId),
+}
+
+impl Scope {
+    pub(crate) fn from_columns(kind: &ScopeKind, id: Option<&str>) -> Result<Self, AppError> {
+        let id = || -> Result<ObjectId, AppError> {
+            Ok(ObjectId::parse_str(id.ok_or(anyhow::anyhow!(
+                "Scope of kind {:?} has no id!",
+                kind
+            ))?)?)
+        };
+        Ok(match kind {
+            ScopeKind::Global => Self::Global,
+            ScopeKind::Magazine => Self::Magazine(id()?),
+            ScopeKind::Category => Self::Category(id()?),
+        })
+    }
+
+    pub(crate) fn into_columns(self) -> (ScopeKind, Option<String>) {
+        match self {
+            Self::Global => (ScopeKind::Global, None),
+            Self::Magazine(id) => (ScopeKind::Magazine, Some(id.to_hex())),
+            Self::Category(id) => (ScopeKind::Category, Some(id.to_hex())),
+        }
+    }
+}
+
+pub(crate) fn role_permissions(role: &Role) -> &'static [Permission] {
+    match role {
+        Role::Administrator => &[
+            Permission::Managing,
+            Permission::Publishing,
+            Permission::Reviewing,
+            Permission::Auditing,
+        ],
+        Role::Editor => &[
+            Permission::Publishing,
+            Permission::Reviewing,
+            Permission::Auditing,
+        ],
+        Role::Reviewer => &[Permission::Reviewing],
+        Role::Auditor => &[Permission::Auditing],
+    }
+}
+
+pub(crate) type Permissions = BTreeSet<(Permission, Scope)>;
+
+/// Everything the roles granted to `email` allow, each within its scope.
+pub(crate) async fn load_permissions(
+    state: &AppState,
+    email: &str,
+) -> Result<Permissions, AppError> {
+    let mut res = Permissions::new();
+    for grant in RoleGrant::find()
+        .filter(role_grant::Column::Email.eq(email))
+        .all(&state.sql_db)
+        .await?
+    {
+        let scope = Scope::from_columns(&grant.scope_kind, grant.scope_id.as_deref())?;
+        res.extend(
+            role_permissions(&grant.role)
+                .iter()
+                .map(|permission| (*permission, scope)),
+        );
+    }
+    Ok(res)
+}
+
+/// Every category listing `thesis` or its magazine, and every category above those.
+async fn find_categories(
+    state: &AppState,
+    thesis: &Thesis,
+) -> Result<BTreeSet<ObjectId>, AppError> {
+    let mut res = BTreeSet::new();
+    let mut filter = doc! {
+        field!(thesis_ids in Category): thesis.id._id
+    };
+    if let Some(magazine_id) = thesis.magazine_id {
+        filter = doc! {
+            "$or": [
+                filter,
+                { field!(magazine_ids in Category): magazine_id },
+            ]
+        };
+    }
+    loop {
+        let found: Vec<ObjectId> = state
+            .mongo_db
+            .repository::<Category>()
+            .find(filter, None)
+            .await?
+            .map_ok(|category| category.meta._id)
+            .try_collect()
+            .await?;
+        let found: Vec<ObjectId> = found.into_iter().filter(|id| res.insert(*id)).collect();
+        if found.is_empty() {
+            return Ok(res);
+        }
+        filter = doc! {
+            field!(sub_category_ids in Category): {
+                "$in": found
+            }
+        };
+    }
+}
+
+/// What an API token may do besides reading.
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TokenScope {
+    Write,
+    Publishing,
+    Managing,
+}
+
+/// All a session remembers; permissions are looked up afresh on every request.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct SessionUser {
+    id: ObjectId,
+    email: String,
+    #[serde(default)]
+    logged_in_at: Option<chrono::NaiveDateTime>,
+}
+
 #[derive(Default)]
 #[derive(Eq, PartialEq)]
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 #[derive(Debug)]
 pub(crate) struct AuthInfo {
     pub(crate) id: Option<ObjectId>,
-    roles: [bool; 2],
+    permissions: Permissions,
+    pub(crate) by_token: bool,
 }
 
 impl AuthInfo {
     pub(crate) fn id(&self) -> Result<ObjectId, AppError> {
         self.id.ok_or(AppError::Forbidden("Log in!".to_string()))
     }
-    pub(crate) fn permitted(&self, role: Permission) -> bool {
-        self.roles
-            .get(role as usize)
-            .map(bool::to_owned)
-            .unwrap_or_default()
+    pub(crate) fn permitted(&self, permission: Permission, scope: Scope) -> bool {
+        self.permissions.contains(&(permission, Scope::Global))
+            || self.permissions.contains(&(permission, scope))
+    }
+
+    /// Like `permitted` within the magazine of `thesis`, but grants on categories count as well.
+    pub(crate) async fn permitted_on(
+        &self,
+        state: &AppState,
+        permission: Permission,
+        thesis: &Thesis,
+    ) -> Result<bool, AppError> {
+        if self.permitted(
+            permission,
+            thesis.magazine_id.map_or(Scope::Global, Scope::Magazine),
+        ) {
+            return Ok(true);
+        }
+        if !self
+            .permissions
+            .iter()
+            .any(|(granted, scope)| *granted == permission && matches!(scope, Scope::Category(_)))
+        {
+            return Ok(false);
+        }
+        Ok(find_categories(state, thesis).await?.into_iter().any(|id| {
+            self.permissions
+                .contains(&(permission, Scope::Category(id)))
+        }))
+    }
+
+    async fn from_token(state: &AppState, token: &str, method: &Method) -> Result<Self, AppError> {
+        let invalid = || AppError::Forbidden("Invalid API token!".to_string());
+        let (token, account) = ApiToken::find()
+            .filter(api_token::Column::TokenHash.eq(secret::digest(token)))
+            .find_also_related(Account)
+            .one(&state.sql_db)
+            .await?
+            .ok_or_else(invalid)?;
+        let account = account.ok_or_else(invalid)?;
+        let now = chrono::Utc::now().naive_utc();
+        if token.revoked_at.is_some()
+            || matches!(token.expires_at, Some(expires_at) if expires_at <= now)
+            || !account.is_verified
+            || !account.is_active
+        {
+            return Err(invalid());
+        }
+        let scopes: BTreeSet<TokenScope> = serde_json::from_value(token.scopes.clone())?;
+        if !(method == Method::GET || method == Method::HEAD || scopes.contains(&TokenScope::Write))
+        {
+            return Err(AppError::Forbidden(
+                "This API token is read-only!".to_string(),
+            ));
+        }
+
+        let profile = state
+            .mongo_db
+            .repository::<Profile>()
+            .find_one(
+                doc! {
+                    field!(email in ProfileId): to_bson(&account.email)?
+                },
+                None,
+            )
+            .await?
+            .ok_or_else(invalid)?;
+        let mut token: api_token::ActiveModel = token.into();
+        token.last_used_at = ActiveValue::Set(Some(now));
+        token.update(&state.sql_db).await?;
+        Self::resolve(
+            state,
+            profile.public_profile.id._id,
+            &account,
+            Some(&scopes),
+        )
+        .await
+    }
+
+    async fn from_session(state: &AppState, user: SessionUser) -> Result<Self, AppError> {
+        match Account::find_by_id(user.email).one(&state.sql_db).await? {
+            // Sessions that predate a revocation are treated as logged out.
+            Some(account)
+                if account.is_active
+                    && account.sessions_revoked_at.is_none_or(|revoked_at| {
+                        user.logged_in_at
+                            .is_some_and(|logged_in_at| logged_in_at >= revoked_at)
+                    }) =>
+            {
+                Self::resolve(state, user.id, &account, None).await
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    async fn resolve(
+        state: &AppState,
+        id: ObjectId,
+        account: &account::Model,
+        scopes: Option<&BTreeSet<TokenScope>>,
+    ) -> Result<Self, AppError> {
+        let trusted = account.totp_enabled || !state.totp_required;
+        let permissions = load_permissions(state, &account.email)
+            .await?
+            .into_iter()
+            .filter(|(permission, _)| {
+                (trusted || !permission.is_privileged())
+                    && scopes.is_none_or(|scopes| match permission {
+                        Permission::Managing => scopes.contains(&TokenScope::Managing),
+                        Permission::Publishing => scopes.contains(&TokenScope::Publishing),
+                        Permission::Reviewing | Permission::Auditing => true,
+                    })
+            })
+            .collect();
+        Ok(Self {
+            id: Some(id),
+            permissions,
+            by_token: scopes.is_some(),
+        })
     }
 }
 
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthInfo
 where
+    AppState: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = AppError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        Ok(ReadableSession::from_request_parts(parts, state)
+        if let Some(TypedHeader(Authorization(bearer))) =
+            Option::<TypedHeader<Authorization<Bearer>>>::from_request_parts(parts, state).await?
+        {
+            return Self::from_token(&AppState::from_ref(state), bearer.token(), &parts.method)
+                .await;
+        }
+        match ReadableSession::from_request_parts(parts, state)
             .await?
-            .get::<Self>("auth_info")
-            .unwrap_or(AuthInfo::default()))
+            .get::<SessionUser>("auth_info")
+        {
+            Some(user) => Self::from_session(&AppState::from_ref(state), user).await,
+            None => Ok(Self::default()),
+        }
     }
 }
 
@@ -60,17 +354,13 @@ where
 pub(crate) struct AuthInfoStorage(WritableSession);
 
 impl AuthInfoStorage {
-    pub(crate) fn store(
-        &mut self,
-        id: ObjectId,
-        is_administrator: bool,
-        is_editor: bool,
-    ) -> Result<(), AppError> {
+    pub(crate) fn store(&mut self, id: ObjectId, email: String) -> Result<(), AppError> {
         self.0.insert(
             "auth_info",
-            AuthInfo {
-                id: Some(id),
-                roles: [is_administrator, is_editor],
+            SessionUser {
+                id,
+                email,
+                logged_in_at: Some(chrono::Utc::now().naive_utc()),
             },
         )?;
         Ok(())
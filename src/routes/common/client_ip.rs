@@ -0,0 +1,43 @@
+use std::net::{IpAddr, SocketAddr};
+
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts};
+use axum::http::request::Parts;
+
+use crate::routes::common::err::AppError;
+use crate::state::AppState;
+
+/// The address a request comes from, looking through the proxies the deployment trusts.
+pub(crate) struct ClientIp(pub(crate) IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        let ConnectInfo(addr) =
+            ConnectInfo::<SocketAddr>::from_request_parts(parts, &state).await?;
+        let mut ip = addr.ip();
+        // Walk back from the nearest hop; only a trusted proxy may speak for the one before it.
+        for value in parts.headers.get_all(&state.forwarded_header).iter().rev() {
+            let hops = value
+                .to_str()
+                .map_err(|_| AppError::BadRequest("Malformed forwarded header!".to_string()))?;
+            for hop in hops.rsplit(',') {
+                if !state.trusted_proxies.contains(&ip) {
+                    return Ok(Self(ip));
+                }
+                ip = hop
+                    .trim()
+                    .parse()
+                    .map_err(|_| AppError::BadRequest("Malformed forwarded header!".to_string()))?;
+            }
+        }
+        Ok(Self(ip))
+    }
+}
@@ -7,6 +7,7 @@ pub(crate) enum AppError {
     Conflict(String),
     Forbidden(String),
     NotFound(String),
+    TooManyRequests(String),
 }
 
 impl IntoResponse for AppError {
@@ -17,6 +18,7 @@ impl IntoResponse for AppError {
             Self::Conflict(e) => (StatusCode::BAD_REQUEST, e),
             Self::Forbidden(e) => (StatusCode::FORBIDDEN, e),
             Self::NotFound(e) => (StatusCode::NOT_FOUND, e),
+            Self::TooManyRequests(e) => (StatusCode::TOO_MANY_REQUESTS, e),
         }
         .into_response()
     }
@@ -0,0 +1,22 @@
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::{AsyncTransport, Message};
+
+use crate::routes::common::err::AppError;
+use crate::state::AppState;
+
+pub(crate) async fn send(
+    state: &AppState,
+    to: &lettre::Address,
+    subject: &str,
+    body: String,
+) -> Result<(), AppError> {
+    let message = Message::builder()
+        .from(state.sender.as_ref().clone())
+        .to(Mailbox::new(None, to.clone()))
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)?;
+    state.smtp.send(message).await?;
+    Ok(())
+}
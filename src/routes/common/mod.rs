@@ -1,6 +1,13 @@
+pub(super) mod audit;
 pub(super) mod auth;
+pub(super) mod client_ip;
 pub(super) mod err;
+pub(super) mod mail;
+pub(crate) mod notify;
 pub(super) mod query;
+pub(super) mod secret;
+pub(crate) mod session;
+pub(super) mod xml;
 
 pub(crate) const DISPOSITION_PREFIX: &str = "attachment; filename=\"";
 pub(crate) const DISPOSITION_SUFFIX: &str = "\"";
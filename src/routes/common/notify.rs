@@ -0,0 +1,156 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use futures_util::TryStreamExt;
+use mongodm::mongo::change_stream::{event::ChangeStreamEvent, event::ResumeToken, ChangeStream};
+use mongodm::mongo::options::ChangeStreamOptions;
+use mongodm::prelude::{MongoDatabase, MongoError, ObjectId};
+use mongodm::{doc, field, ToRepository};
+use tokio::sync::broadcast::Sender;
+
+use crate::mongo_entities::notification::{Notification, NotificationKind};
+use crate::mongo_entities::profile::Profile;
+use crate::mongo_entities::thesis::Thesis;
+use crate::routes::common::{err::AppError, mail};
+use crate::state::AppState;
+
+fn subject(kind: NotificationKind) -> &'static str {
+    match kind {
+        NotificationKind::ReviewerAssigned => "You have been asked to review a thesis",
+        NotificationKind::ReviewSubmitted => "Your thesis has been reviewed",
+        NotificationKind::VersionPassed => "Your thesis has been accepted",
+        NotificationKind::VersionRejected => "Your thesis has been rejected",
+        NotificationKind::CommentPosted => "Your thesis has a new comment",
+        NotificationKind::ReplyPosted => "Your comment has a new reply",
+    }
+}
+
+/// The owner and the authors of a thesis.
+pub(crate) fn authors(thesis: &Thesis) -> BTreeSet<ObjectId> {
+    std::iter::once(thesis.id.owner_id)
+        .chain(thesis.author_ids.iter().copied())
+        .collect()
+}
+
+/// Notifies everyone in `recipient_ids` but the actor, live and by email too for whoever asked for it.
+pub(crate) async fn notify(
+    state: &AppState,
+    actor_id: ObjectId,
+    recipient_ids: impl IntoIterator<Item = ObjectId>,
+    kind: NotificationKind,
+    target_id: ObjectId,
+    message: String,
+) -> Result<(), AppError> {
+    let recipient_ids: BTreeSet<_> = recipient_ids
+        .into_iter()
+        .filter(|id| *id != actor_id)
+        .collect();
+    for recipient_id in recipient_ids {
+        let Some(profile) = state
+            .mongo_db
+            .repository::<Profile>()
+            .find_one(
+                doc! {
+                    "_id": recipient_id
+                },
+                None,
+            )
+            .await?
+        else {
+            continue;
+        };
+        let notification = Notification {
+            _id: ObjectId::new(),
+            recipient_id,
+            kind,
+            target_id,
+            message: message.clone(),
+            created_at: chrono::Utc::now(),
+            read_at: None,
+            push: profile.setting.push,
+        };
+        state
+            .mongo_db
+            .repository::<Notification>()
+            .insert_one(&notification, None)
+            .await?;
+        if profile.setting.email_notice {
+            let state = state.clone();
+            let body = format!("{}\n\n{}", message, state.clt_addr);
+            // An unreachable mailbox must not undo what has already happened.
+            tokio::spawn(async move {
+                let _ = mail::send(
+                    &state,
+                    &profile.public_profile.id.email,
+                    subject(kind),
+                    body,
+                )
+                .await;
+            });
+        }
+    }
+    Ok(())
+}
+
+/// How long to wait before watching again when the change stream breaks off.
+const RELAY_RETRY: std::time::Duration = std::time::Duration::from_secs(1);
+
+async fn watch(
+    db: &MongoDatabase,
+    resume_after: Option<ResumeToken>,
+) -> Result<ChangeStream<ChangeStreamEvent<Notification>>, MongoError> {
+    db.repository::<Notification>()
+        .watch(
+            [doc! {
+                "$match": {
+                    "operationType": "insert",
+                    format!("fullDocument.{}", field!(push in Notification)): true
+                }
+            }],
+            ChangeStreamOptions::builder()
+                .resume_after(resume_after)
+                .build(),
+        )
+        .await
+}
+
+/// Feeds `events` with the notifications to push that any replica stores, which needs MongoDB to
+/// run as a replica set; fails right away if it cannot watch at all. Notifications stored while
+/// a lost stream could only be restarted afresh are not pushed.
+pub(crate) async fn relay(
+    db: MongoDatabase,
+    events: Sender<Arc<Notification>>,
+) -> Result<(), MongoError> {
+    let mut changes = watch(&db, None).await?;
+    tokio::spawn(async move {
+        loop {
+            match changes.try_next().await {
+                Ok(Some(change)) => {
+                    if let Some(notification) = change.full_document {
+                        // Nobody may be listening at all.
+                        let _ = events.send(Arc::new(notification));
+                    }
+                }
+                // Elections and restarts interrupt the stream; pick up where it stopped.
+                Ok(None) | Err(_) => {
+                    let mut resume_after = changes.resume_token();
+                    loop {
+                        tokio::time::sleep(RELAY_RETRY).await;
+                        match watch(&db, resume_after.clone()).await {
+                            Ok(res) => {
+                                changes = res;
+                                break;
+                            }
+                            // The token may have expired; then only a fresh watch gets anywhere.
+                            Err(err) => {
+                                tracing::warn!("Cannot resume watching notifications: {}", err);
+                                resume_after = None;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    });
+    Ok(())
+}
@@ -1,9 +1,32 @@
-use axum::http::{header, HeaderName, HeaderValue};
-use serde::Deserialize;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use axum::http::{header, HeaderName, HeaderValue, Uri};
+use axum::response::{IntoResponseParts, ResponseParts};
+use base64::Engine;
+use mongodm::bson::{doc, oid::ObjectId, to_bson, to_document, Bson, Document};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::routes::common::err::AppError;
+
+const DEFAULT_LIMIT: i64 = 20;
+/// Keeps one page from costing an unbounded number of documents.
+const LIMIT_MAX: i64 = 100;
+
+fn default_limit() -> i64 {
+    DEFAULT_LIMIT
+}
+
+fn bounded_limit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    Ok(i64::deserialize(deserializer)?.clamp(-LIMIT_MAX, LIMIT_MAX))
+}
 
 #[derive(Deserialize)]
 pub(crate) struct AppQuery {
+    #[serde(default)]
     pub(crate) offset: u64,
+    /// At most `LIMIT_MAX` either way.
+    #[serde(default = "default_limit", deserialize_with = "bounded_limit")]
     pub(crate) limit: i64,
 }
 
@@ -15,17 +38,290 @@ impl AppQuery {
                 HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
             ),
             (
-                HeaderName::from_static("X-Pagination-Count"),
+                HeaderName::from_static("x-pagination-count"),
                 HeaderValue::from_str(&count.to_string()).unwrap(),
             ),
             (
-                HeaderName::from_static("X-Pagination-Offset"),
+                HeaderName::from_static("x-pagination-offset"),
                 HeaderValue::from_str(&self.offset.to_string()).unwrap(),
             ),
             (
-                HeaderName::from_static("X-Pagination-Limit"),
+                HeaderName::from_static("x-pagination-limit"),
                 HeaderValue::from_str(&self.limit.to_string()).unwrap(),
             ),
         ]
     }
 }
+
+/// Direction of the `sort` key of a list endpoint.
+#[derive(Deserialize)]
+#[derive(Copy, Clone)]
+#[derive(Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Order {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl Order {
+    pub(crate) fn direction(self) -> i32 {
+        match self {
+            Self::Asc => 1,
+            Self::Desc => -1,
+        }
+    }
+}
+
+/// Turns a comma-separated parameter such as `keywords=rust,web` into an `$in` operand.
+pub(crate) fn one_of<T>(name: &str, value: &str) -> Result<Document, AppError>
+where
+    T: FromStr + Into<Bson>,
+    T::Err: Display,
+{
+    let values = value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            item.parse::<T>().map(Into::into).map_err(|err| {
+                AppError::BadRequest(format!("Invalid {} {:?}: {}!", name, item, err))
+            })
+        })
+        .collect::<Result<Vec<Bson>, _>>()?;
+    if values.is_empty() {
+        return Err(AppError::BadRequest(format!("Empty list of {}!", name)));
+    }
+    Ok(mongodm::bson::doc! {
+        "$in": values
+    })
+}
+
+/// `since` is inclusive and `until` exclusive; `None` when neither is given.
+pub(crate) fn between<T: Serialize>(
+    since: Option<T>,
+    until: Option<T>,
+) -> Result<Option<Document>, AppError> {
+    let mut res = Document::new();
+    if let Some(since) = since {
+        res.insert("$gte", to_bson(&since)?);
+    }
+    if let Some(until) = until {
+        res.insert("$lt", to_bson(&until)?);
+    }
+    Ok(Some(res).filter(|res| !res.is_empty()))
+}
+
+/// Where a keyset page starts: the sort key and `_id` of the item next to it.
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+struct Cursor {
+    key: String,
+    value: Bson,
+    id: ObjectId,
+}
+
+impl Cursor {
+    fn of<T: Serialize>(key: &str, item: &T) -> Result<Self, AppError> {
+        let item = to_document(item)?;
+        Ok(Self {
+            key: key.to_string(),
+            value: item.get(key).cloned().unwrap_or(Bson::Null),
+            id: item
+                .get_object_id("_id")
+                .map_err(|_| anyhow::anyhow!("Cannot get id of a listed item!"))?,
+        })
+    }
+
+    fn encode(&self) -> Result<String, AppError> {
+        Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(mongodm::bson::to_vec(self)?))
+    }
+
+    fn decode(cursor: &str) -> Result<Self, AppError> {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| mongodm::bson::from_slice(&bytes).ok())
+            .ok_or(AppError::BadRequest(format!("Invalid cursor {}!", cursor)))
+    }
+}
+
+/// Cursors handed out in `Link` headers; each replaces `offset` when given.
+#[derive(Deserialize)]
+pub(crate) struct KeysetQuery {
+    #[serde(default)]
+    after: Option<String>,
+    #[serde(default)]
+    before: Option<String>,
+}
+
+/// One page of a list sorted by `key` and then `_id`, which stays put while items are added.
+pub(crate) struct Page {
+    key: &'static str,
+    order: Order,
+    cursor: Option<Cursor>,
+    backwards: bool,
+}
+
+impl Page {
+    pub(crate) fn new(
+        keyset: &KeysetQuery,
+        key: &'static str,
+        order: Order,
+    ) -> Result<Self, AppError> {
+        let (cursor, backwards) = match (&keyset.after, &keyset.before) {
+            (Some(_), Some(_)) => {
+                return Err(AppError::BadRequest(
+                    "Cannot page both after and before a cursor!".to_string(),
+                ))
+            }
+            (Some(after), None) => (Some(Cursor::decode(after)?), false),
+            (None, Some(before)) => (Some(Cursor::decode(before)?), true),
+            (None, None) => (None, false),
+        };
+        if let Some(cursor) = &cursor {
+            if cursor.key != key {
+                return Err(AppError::BadRequest(format!(
+                    "Cursor was made for sorting by {} instead of {}!",
+                    cursor.key, key
+                )));
+            }
+        }
+        Ok(Self {
+            key,
+            order,
+            cursor,
+            backwards,
+        })
+    }
+
+    /// Reversed when paging backwards, so that the items nearest to the cursor come first.
+    fn direction(&self) -> i32 {
+        if self.backwards {
+            -self.order.direction()
+        } else {
+            self.order.direction()
+        }
+    }
+
+    pub(crate) fn filter(&self, filter: Document) -> Document {
+        let Some(cursor) = &self.cursor else {
+            return filter;
+        };
+        let op = if self.direction() > 0 { "$gt" } else { "$lt" };
+        doc! {
+            "$and": [
+                filter,
+                {
+                    "$or": [
+                        { self.key: { op: &cursor.value } },
+                        { self.key: &cursor.value, "_id": { op: cursor.id } }
+                    ]
+                }
+            ]
+        }
+    }
+
+    pub(crate) fn sort(&self) -> Document {
+        doc! {
+            self.key: self.direction(),
+            "_id": self.direction()
+        }
+    }
+
+    pub(crate) fn skip(&self, query: &AppQuery) -> u64 {
+        if self.cursor.is_some() {
+            0
+        } else {
+            query.offset
+        }
+    }
+
+    /// Asks for one item more than `limit` to learn whether another page follows.
+    pub(crate) fn limit(&self, query: &AppQuery) -> i64 {
+        query.limit.abs() + 1
+    }
+
+    /// Trims `res` back to `limit` in list order and links the neighbouring pages.
+    pub(crate) fn finish<T: Serialize>(
+        &self,
+        query: &AppQuery,
+        uri: &Uri,
+        res: &mut Vec<T>,
+    ) -> Result<Links, AppError> {
+        let limit = usize::try_from(query.limit.abs())?;
+        let has_more = res.len() > limit;
+        res.truncate(limit);
+        if self.backwards {
+            res.reverse();
+        }
+        let (has_prev, has_next) = match (&self.cursor, self.backwards) {
+            (None, _) => (query.offset > 0, has_more),
+            (Some(_), false) => (true, has_more),
+            (Some(_), true) => (has_more, true),
+        };
+
+        let mut links = Vec::new();
+        if has_prev {
+            if let Some(first) = res
+                .first()
+                .map(|item| Cursor::of(self.key, item))
+                .transpose()?
+            {
+                links.push(link(uri, "before", &first, "prev")?);
+            } else if let Some(cursor) = &self.cursor {
+                links.push(link(uri, "before", cursor, "prev")?);
+            }
+        }
+        if has_next {
+            if let Some(last) = res
+                .last()
+                .map(|item| Cursor::of(self.key, item))
+                .transpose()?
+            {
+                links.push(link(uri, "after", &last, "next")?);
+            } else if let Some(cursor) = &self.cursor {
+                links.push(link(uri, "after", cursor, "next")?);
+            }
+        }
+        Ok(Links(if links.is_empty() {
+            None
+        } else {
+            Some(HeaderValue::from_str(&links.join(", "))?)
+        }))
+    }
+}
+
+/// The request URI with its paging replaced by `param=cursor`.
+fn link(uri: &Uri, param: &str, cursor: &Cursor, rel: &str) -> Result<String, AppError> {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    for (name, value) in url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes()) {
+        if !matches!(name.as_ref(), "offset" | "after" | "before") {
+            query.append_pair(&name, &value);
+        }
+    }
+    query.append_pair(param, &cursor.encode()?);
+    Ok(format!(
+        "<{}?{}>; rel=\"{}\"",
+        uri.path(),
+        query.finish(),
+        rel
+    ))
+}
+
+/// The `Link` header of a page, left out when there is nowhere to go.
+pub(crate) struct Links(Option<HeaderValue>);
+
+impl IntoResponseParts for Links {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        if let Some(links) = self.0 {
+            res.headers_mut().insert(header::LINK, links);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests;
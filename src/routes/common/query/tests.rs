@@ -0,0 +1,31 @@
+use axum::extract::{FromRequestParts, Query};
+use axum::http::Request;
+
+use super::AppQuery;
+
+async fn query(uri: &str) -> AppQuery {
+    let (mut parts, _) = Request::get(uri).body(()).unwrap().into_parts();
+    let Query(res) = Query::from_request_parts(&mut parts, &()).await.unwrap();
+    res
+}
+
+#[tokio::test]
+async fn pagenates() {
+    let headers = query("/theses?offset=40").await.pagenate(100);
+    let value = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_str().unwrap().to_string())
+    };
+    assert_eq!(value("x-pagination-count").as_deref(), Some("100"));
+    assert_eq!(value("x-pagination-offset").as_deref(), Some("40"));
+    assert_eq!(value("x-pagination-limit").as_deref(), Some("20"));
+}
+
+#[tokio::test]
+async fn bounds_limits() {
+    assert_eq!(query("/theses?limit=1000").await.limit, 100);
+    assert_eq!(query("/theses?limit=-1000").await.limit, -100);
+    assert_eq!(query("/theses?limit=5").await.limit, 5);
+}
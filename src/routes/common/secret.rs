@@ -0,0 +1,14 @@
+use sha2::{Digest, Sha256};
+
+/// A fresh random secret, hex-encoded so that it fits in links and headers.
+pub(crate) fn generate() -> String {
+    rand::random::<[u8; 32]>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Secrets are only ever stored as this digest.
+pub(crate) fn digest(secret: &str) -> Vec<u8> {
+    Sha256::digest(secret.as_bytes()).to_vec()
+}
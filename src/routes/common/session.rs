@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use axum_sessions::async_session::{Session, SessionStore};
+use mongodm::bson::DateTime;
+use mongodm::prelude::{MongoDatabase, MongoReplaceOptions};
+use mongodm::{doc, ToRepository};
+
+use crate::mongo_entities::session::StoredSession;
+
+/// Keeps sessions in MongoDB so that they survive restarts and are shared between replicas.
+#[derive(Clone, Debug)]
+pub(crate) struct MongoSessionStore(MongoDatabase);
+
+impl MongoSessionStore {
+    pub(crate) fn new(db: MongoDatabase) -> Self {
+        Self(db)
+    }
+}
+
+#[async_trait]
+impl SessionStore for MongoSessionStore {
+    async fn load_session(
+        &self,
+        cookie_value: String,
+    ) -> axum_sessions::async_session::Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let res = self
+            .0
+            .repository::<StoredSession>()
+            .find_one(
+                doc! {
+                    "_id": id
+                },
+                None,
+            )
+            .await?;
+        Ok(res.and_then(|stored| stored.session.validate()))
+    }
+
+    async fn store_session(
+        &self,
+        session: Session,
+    ) -> axum_sessions::async_session::Result<Option<String>> {
+        let id = session.id().to_string();
+        self.0
+            .repository::<StoredSession>()
+            .replace_one(
+                doc! {
+                    "_id": &id
+                },
+                StoredSession {
+                    _id: id.clone(),
+                    session: session.clone(),
+                    expires_at: session
+                        .expiry()
+                        .map(|expiry| DateTime::from_chrono(*expiry)),
+                },
+                MongoReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> axum_sessions::async_session::Result {
+        self.0
+            .repository::<StoredSession>()
+            .delete_one(
+                doc! {
+                    "_id": session.id()
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> axum_sessions::async_session::Result {
+        self.0
+            .repository::<StoredSession>()
+            .delete_many(doc! {}, None)
+            .await?;
+        Ok(())
+    }
+}
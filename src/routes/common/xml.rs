@@ -0,0 +1,17 @@
+/// Escapes `text` for XML element content and attribute values alike.
+pub(crate) fn escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            // Not allowed in XML 1.0 at all, not even as character references.
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => res.push(c),
+        }
+    }
+    res
+}
@@ -0,0 +1,172 @@
+use std::fmt::Write;
+
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderName, HeaderValue};
+use axum::{debug_handler, routing, Router};
+use mongodm::prelude::ObjectId;
+
+use crate::mongo_entities::paper_collection::Magazine;
+use crate::mongo_entities::thesis::Thesis;
+use crate::routes::common::auth::{AuthInfo, Permission};
+use crate::routes::common::err::AppError;
+use crate::routes::common::xml::escape;
+use crate::routes::common::{DISPOSITION_PREFIX, DISPOSITION_SUFFIX};
+use crate::routes::thesis::{
+    find_author_names, find_magazine, find_publication_date, find_thesis_by_id, split_name,
+};
+use crate::state::AppState;
+
+const SCHEMA_VERSION: &str = "5.3.1";
+
+fn write_person_name(res: &mut String, name: &str, sequence: &str) -> std::fmt::Result {
+    let (given_name, surname) = split_name(name);
+    write!(
+        res,
+        "<person_name sequence=\"{}\" contributor_role=\"author\">",
+        sequence
+    )?;
+    if let Some(given_name) = given_name {
+        write!(res, "<given_name>{}</given_name>", escape(given_name))?;
+    }
+    write!(res, "<surname>{}</surname></person_name>", escape(surname))
+}
+
+/// A journal article deposit in the Crossref schema, to be uploaded by the depositor as is.
+fn render(
+    state: &AppState,
+    thesis: &Thesis,
+    doi: &str,
+    magazine: &Magazine,
+    authors: &[String],
+    published_at: chrono::DateTime<chrono::Utc>,
+) -> Result<String, std::fmt::Error> {
+    let now = chrono::Utc::now();
+    let mut res = String::new();
+    write!(
+        res,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <doi_batch version=\"{0}\" xmlns=\"http://www.crossref.org/schema/{0}\" \
+        xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+        xmlns:jats=\"http://www.ncbi.nlm.nih.gov/JATS1\" \
+        xsi:schemaLocation=\"http://www.crossref.org/schema/{0} \
+        https://www.crossref.org/schemas/crossref{0}.xsd\">",
+        SCHEMA_VERSION
+    )?;
+    write!(
+        res,
+        "<head><doi_batch_id>{}-{}</doi_batch_id><timestamp>{}</timestamp>\
+        <depositor><depositor_name>{}</depositor_name><email_address>{}</email_address></depositor>\
+        <registrant>{}</registrant></head>",
+        thesis.id._id,
+        now.timestamp(),
+        now.format("%Y%m%d%H%M%S%3f"),
+        escape(
+            state
+                .crossref_depositor
+                .name
+                .as_deref()
+                .unwrap_or(state.crossref_depositor.email.as_ref())
+        ),
+        escape(state.crossref_depositor.email.as_ref()),
+        escape(
+            state
+                .crossref_depositor
+                .name
+                .as_deref()
+                .unwrap_or(&magazine.meta.name)
+        ),
+    )?;
+
+    write!(
+        res,
+        "<body><journal><journal_metadata><full_title>{}</full_title>",
+        escape(&magazine.meta.name)
+    )?;
+    if let Some(abbr) = magazine.abbr.first() {
+        write!(res, "<abbrev_title>{}</abbrev_title>", escape(abbr))?;
+    }
+    write!(
+        res,
+        "</journal_metadata><journal_article publication_type=\"full_text\">\
+        <titles><title>{}</title></titles>",
+        escape(&thesis.title)
+    )?;
+    if !authors.is_empty() {
+        res.push_str("<contributors>");
+        for (i, name) in authors.iter().enumerate() {
+            write_person_name(&mut res, name, if i == 0 { "first" } else { "additional" })?;
+        }
+        res.push_str("</contributors>");
+    }
+    if !thesis.abstraction.is_empty() {
+        write!(
+            res,
+            "<jats:abstract><jats:p>{}</jats:p></jats:abstract>",
+            escape(&thesis.abstraction)
+        )?;
+    }
+    write!(
+        res,
+        "<publication_date media_type=\"online\"><month>{}</month><day>{}</day><year>{}</year>\
+        </publication_date><doi_data><doi>{}</doi><resource>{}/theses/{}</resource></doi_data>\
+        </journal_article></journal></body></doi_batch>",
+        published_at.format("%m"),
+        published_at.format("%d"),
+        published_at.format("%Y"),
+        escape(doi),
+        escape(&state.clt_addr),
+        thesis.id._id
+    )?;
+    Ok(res)
+}
+
+#[debug_handler]
+async fn deposit(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectId>,
+) -> Result<([(HeaderName, HeaderValue); 2], String), AppError> {
+    let thesis = find_thesis_by_id(&state, id).await?;
+    if !auth_info
+        .permitted_on(&state, Permission::Publishing, &thesis)
+        .await?
+    {
+        return Err(AppError::Forbidden("you are not a editor".to_string()));
+    }
+    let doi = thesis
+        .doi
+        .clone()
+        .ok_or(AppError::BadRequest(format!("Thesis {} has no DOI!", id)))?;
+    let published_at = find_publication_date(&state, id).await?;
+    let magazine = find_magazine(
+        &state,
+        thesis.magazine_id.ok_or(AppError::BadRequest(format!(
+            "Thesis {} has no magazine!",
+            id
+        )))?,
+    )
+    .await?;
+    let authors = find_author_names(&state, &thesis).await?;
+    let res = render(&state, &thesis, &doi, &magazine, &authors, published_at)
+        .map_err(anyhow::Error::from)?;
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/xml"),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&format!(
+                    "{}{}.xml{}",
+                    DISPOSITION_PREFIX, id, DISPOSITION_SUFFIX
+                ))?,
+            ),
+        ],
+        res,
+    ))
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new().route("/:id/crossref", routing::get(deposit))
+}
@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, OriginalUri, Path, State};
+use axum::headers::{ETag, IfModifiedSince, IfNoneMatch, LastModified};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{debug_handler, routing, Router, TypedHeader};
+use futures_util::TryStreamExt;
+use mongodm::bson::{to_bson, Document};
+use mongodm::prelude::{MongoFindOneOptions, MongoFindOptions, ObjectId};
+use mongodm::{doc, field, ToRepository};
+use serde::Deserialize;
+
+use crate::mongo_entities::paper_collection::Category;
+use crate::mongo_entities::profile::Profile;
+use crate::mongo_entities::thesis::{Thesis, ThesisId, Version, VersionState};
+use crate::routes::common::err::AppError;
+use crate::routes::common::xml::escape;
+use crate::routes::thesis::{find_author_names, find_magazine};
+use crate::state::AppState;
+
+const FEED_SIZE: i64 = 50;
+
+#[derive(Deserialize)]
+#[derive(Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+impl FeedFormat {
+    fn mime(self) -> &'static str {
+        match self {
+            Self::Atom => "application/atom+xml; charset=utf-8",
+            Self::Rss => "application/rss+xml; charset=utf-8",
+        }
+    }
+}
+
+/// What a feed is about; its entries are the latest passed theses matching `filter`.
+struct Channel {
+    title: String,
+    filter: Document,
+}
+
+/// A passed thesis together with the version that made it pass.
+struct Entry {
+    thesis: Thesis,
+    version: Version,
+    authors: Vec<String>,
+    /// Of the version's file, in bytes.
+    length: u64,
+}
+
+/// The request validators of a feed.
+struct Conditions {
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Conditions
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let if_none_match =
+            Option::<TypedHeader<IfNoneMatch>>::from_request_parts(parts, state).await?;
+        let if_modified_since =
+            Option::<TypedHeader<IfModifiedSince>>::from_request_parts(parts, state).await?;
+        Ok(Self {
+            if_none_match: if_none_match.map(|TypedHeader(header)| header),
+            if_modified_since: if_modified_since.map(|TypedHeader(header)| header),
+        })
+    }
+}
+
+impl Conditions {
+    /// `If-Modified-Since` only counts without `If-None-Match`, as RFC 9110 says.
+    fn is_fresh(&self, etag: &ETag, last_modified: SystemTime) -> bool {
+        match (&self.if_none_match, &self.if_modified_since) {
+            (Some(if_none_match), _) => !if_none_match.precondition_passes(etag),
+            (None, Some(if_modified_since)) => !if_modified_since.is_modified(last_modified),
+            (None, None) => false,
+        }
+    }
+}
+
+/// Sizes of the stored files by their ids, looked up together.
+async fn find_lengths(
+    state: &AppState,
+    ids: impl IntoIterator<Item = ObjectId>,
+) -> Result<HashMap<ObjectId, u64>, AppError> {
+    let res = state
+        .mongo_db
+        .gridfs_bucket(None)
+        .find(
+            doc! {
+                "_id": {
+                    "$in": ids.into_iter().collect::<Vec<_>>()
+                }
+            },
+            None,
+        )
+        .await?
+        .try_filter_map(
+            |file| async move { Ok(file.id.as_object_id().map(|id| (id, file.length))) },
+        )
+        .try_collect()
+        .await?;
+    Ok(res)
+}
+
+async fn find_version(state: &AppState, thesis: &Thesis) -> Result<Option<Version>, AppError> {
+    let res = state
+        .mongo_db
+        .repository::<Version>()
+        .find_one(
+            doc! {
+                field!(thesis_id in Version): thesis.id._id,
+                field!(state in Version): to_bson(&VersionState::Passed(true))?
+            },
+            MongoFindOneOptions::builder()
+                .sort(doc! {
+                    field!(major_num in Version): -1
+                })
+                .build(),
+        )
+        .await?;
+    Ok(res)
+}
+
+fn write_atom(
+    state: &AppState,
+    res: &mut String,
+    channel: &Channel,
+    self_url: &str,
+    updated_at: chrono::DateTime<chrono::Utc>,
+    entries: &[Entry],
+) -> std::fmt::Result {
+    write!(
+        res,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <feed xmlns=\"http://www.w3.org/2005/Atom\"><title>{}</title><id>{}</id>\
+        <updated>{}</updated><link rel=\"self\" href=\"{}\"/><link href=\"{}\"/>",
+        escape(&channel.title),
+        escape(self_url),
+        updated_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        escape(self_url),
+        escape(&state.clt_addr)
+    )?;
+    for Entry {
+        thesis,
+        version,
+        authors,
+        length,
+    } in entries
+    {
+        write!(
+            res,
+            "<entry><title>{}</title><id>{}/versions/{}</id><updated>{}</updated>\
+            <published>{}</published><link href=\"{}/theses/{}\"/>\
+            <link rel=\"enclosure\" type=\"application/pdf\" \
+            href=\"{}/files/{}\" length=\"{}\"/>",
+            escape(&thesis.title),
+            escape(&state.srv_url),
+            version._id,
+            thesis
+                .last_modified_at()
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            version
+                .uploaded_at
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            escape(&state.clt_addr),
+            thesis.id._id,
+            escape(&state.srv_url),
+            version.file_id,
+            length
+        )?;
+        for author in authors {
+            write!(res, "<author><name>{}</name></author>", escape(author))?;
+        }
+        for keyword in &thesis.keywords {
+            write!(res, "<category term=\"{}\"/>", escape(keyword))?;
+        }
+        if !version.commit_message.is_empty() {
+            write!(
+                res,
+                "<summary>{}</summary>",
+                escape(&version.commit_message)
+            )?;
+        }
+        if !thesis.abstraction.is_empty() {
+            write!(
+                res,
+                "<content type=\"text\">{}</content>",
+                escape(&thesis.abstraction)
+            )?;
+        }
+        res.push_str("</entry>");
+    }
+    res.push_str("</feed>");
+    Ok(())
+}
+
+/// RSS has no place for abstracts next to descriptions, so items carry commit messages only.
+fn write_rss(
+    state: &AppState,
+    res: &mut String,
+    channel: &Channel,
+    self_url: &str,
+    updated_at: chrono::DateTime<chrono::Utc>,
+    entries: &[Entry],
+) -> std::fmt::Result {
+    write!(
+        res,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" \
+        xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><channel><title>{0}</title>\
+        <link>{1}</link><description>{0}</description><lastBuildDate>{2}</lastBuildDate>\
+        <atom:link rel=\"self\" type=\"application/rss+xml\" href=\"{3}\"/>",
+        escape(&channel.title),
+        escape(&state.clt_addr),
+        updated_at.to_rfc2822(),
+        escape(self_url)
+    )?;
+    for Entry {
+        thesis,
+        version,
+        authors,
+        length,
+    } in entries
+    {
+        write!(
+            res,
+            "<item><title>{}</title><link>{}/theses/{}</link>\
+            <guid isPermaLink=\"false\">{}</guid><pubDate>{}</pubDate>\
+            <enclosure url=\"{}/files/{}\" length=\"{}\" type=\"application/pdf\"/>",
+            escape(&thesis.title),
+            escape(&state.clt_addr),
+            thesis.id._id,
+            version._id,
+            thesis.last_passed_at().to_rfc2822(),
+            escape(&state.srv_url),
+            version.file_id,
+            length
+        )?;
+        for author in authors {
+            write!(res, "<dc:creator>{}</dc:creator>", escape(author))?;
+        }
+        for keyword in &thesis.keywords {
+            write!(res, "<category>{}</category>", escape(keyword))?;
+        }
+        if !version.commit_message.is_empty() {
+            write!(
+                res,
+                "<description>{}</description>",
+                escape(&version.commit_message)
+            )?;
+        }
+        res.push_str("</item>");
+    }
+    res.push_str("</channel></rss>");
+    Ok(())
+}
+
+/// Validators come from the count and the listed theses alone, so unchanged feeds are not rendered.
+async fn respond(
+    state: &AppState,
+    uri: &axum::http::Uri,
+    format: FeedFormat,
+    conditions: Conditions,
+    channel: Channel,
+) -> Result<Response, AppError> {
+    let count = state
+        .mongo_db
+        .repository::<Thesis>()
+        .count_documents(channel.filter.clone(), None)
+        .await?;
+    let theses: Vec<Thesis> = state
+        .mongo_db
+        .repository::<Thesis>()
+        .find(
+            channel.filter.clone(),
+            MongoFindOptions::builder()
+                .sort(doc! {
+                    field!(passed_at in ThesisId): -1,
+                    "_id": -1
+                })
+                .limit(FEED_SIZE)
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    // Edits to any listed thesis count, not only the newest passing.
+    let updated_at = theses
+        .iter()
+        .map(Thesis::last_modified_at)
+        .max()
+        .unwrap_or_default();
+    // HTTP dates have no fractions of a second.
+    let last_modified =
+        SystemTime::UNIX_EPOCH + Duration::from_secs(updated_at.timestamp().max(0) as u64);
+    let etag: ETag = format!(
+        "\"{}-{}-{}\"",
+        count,
+        updated_at.timestamp_millis(),
+        theses
+            .first()
+            .map(|thesis| thesis.id._id.to_hex())
+            .unwrap_or_default()
+    )
+    .parse()?;
+    let headers = (
+        TypedHeader(etag.clone()),
+        TypedHeader(LastModified::from(last_modified)),
+    );
+    if conditions.is_fresh(&etag, last_modified) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    let mut versions = Vec::with_capacity(theses.len());
+    for thesis in theses {
+        // Theses whose passing version was withdrawn since have nothing to link to.
+        if let Some(version) = find_version(state, &thesis).await? {
+            versions.push((thesis, version));
+        }
+    }
+    let lengths = find_lengths(state, versions.iter().map(|(_, version)| version.file_id)).await?;
+    let mut entries = Vec::with_capacity(versions.len());
+    for (thesis, version) in versions {
+        entries.push(Entry {
+            authors: find_author_names(state, &thesis).await?,
+            length: lengths.get(&version.file_id).copied().unwrap_or_default(),
+            thesis,
+            version,
+        });
+    }
+    let self_url = format!("{}{}", state.srv_url, uri);
+    let mut res = String::new();
+    match format {
+        FeedFormat::Atom => write_atom(state, &mut res, &channel, &self_url, updated_at, &entries),
+        FeedFormat::Rss => write_rss(state, &mut res, &channel, &self_url, updated_at, &entries),
+    }
+    .map_err(anyhow::Error::from)?;
+    Ok((
+        headers,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.mime()),
+        )],
+        res,
+    )
+        .into_response())
+}
+
+fn passed() -> Document {
+    doc! {
+        field!(is_passed in ThesisId): true
+    }
+}
+
+#[debug_handler]
+async fn gets(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Path(format): Path<FeedFormat>,
+    conditions: Conditions,
+) -> Result<Response, AppError> {
+    let channel = Channel {
+        title: "Newly published theses".to_string(),
+        filter: passed(),
+    };
+    respond(&state, &uri, format, conditions, channel).await
+}
+
+#[debug_handler]
+async fn gets_by_magazine(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Path((id, format)): Path<(ObjectId, FeedFormat)>,
+    conditions: Conditions,
+) -> Result<Response, AppError> {
+    let magazine = find_magazine(&state, id).await?;
+    let mut filter = passed();
+    filter.insert(field!(magazine_id in Thesis), id);
+    let channel = Channel {
+        title: format!("Newly published in {}", magazine.meta.name),
+        filter,
+    };
+    respond(&state, &uri, format, conditions, channel).await
+}
+
+/// Only public categories have feeds; they list theses directly and by magazine.
+#[debug_handler]
+async fn gets_by_category(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Path((id, format)): Path<(ObjectId, FeedFormat)>,
+    conditions: Conditions,
+) -> Result<Response, AppError> {
+    let category = state
+        .mongo_db
+        .repository::<Category>()
+        .find_one(
+            doc! {
+                "_id": id,
+                field!(is_public in Category): true
+            },
+            None,
+        )
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "Category {} does not exist!",
+            id
+        )))?;
+    let mut filter = passed();
+    filter.insert(
+        "$or",
+        vec![
+            doc! {
+                "_id": {
+                    "$in": to_bson(&category.thesis_ids)?
+                }
+            },
+            doc! {
+                field!(magazine_id in Thesis): {
+                    "$in": to_bson(&category.magazine_ids)?
+                }
+            },
+        ],
+    );
+    let channel = Channel {
+        title: format!("Newly published in {}", category.meta.name),
+        filter,
+    };
+    respond(&state, &uri, format, conditions, channel).await
+}
+
+#[debug_handler]
+async fn gets_by_keyword(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Path((keyword, format)): Path<(String, FeedFormat)>,
+    conditions: Conditions,
+) -> Result<Response, AppError> {
+    let mut filter = passed();
+    filter.insert(field!(keywords in Thesis), &keyword);
+    let channel = Channel {
+        title: format!("Newly published on {}", keyword),
+        filter,
+    };
+    respond(&state, &uri, format, conditions, channel).await
+}
+
+/// Covers theses the profile owns as well as those crediting it.
+#[debug_handler]
+async fn gets_by_author(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Path((id, format)): Path<(ObjectId, FeedFormat)>,
+    conditions: Conditions,
+) -> Result<Response, AppError> {
+    let profile = state
+        .mongo_db
+        .repository::<Profile>()
+        .find_one(
+            doc! {
+                "_id": id
+            },
+            None,
+        )
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "Profile with id {} does not exist!",
+            id
+        )))?;
+    let mut filter = passed();
+    filter.insert(
+        "$or",
+        vec![
+            doc! {
+                field!(owner_id in ThesisId): id
+            },
+            doc! {
+                field!(author_ids in Thesis): id
+            },
+        ],
+    );
+    let channel = Channel {
+        title: format!("Newly published by {}", profile.public_profile.name),
+        filter,
+    };
+    respond(&state, &uri, format, conditions, channel).await
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/:format", routing::get(gets))
+        .route("/magazines/:id/:format", routing::get(gets_by_magazine))
+        .route("/categories/:id/:format", routing::get(gets_by_category))
+        .route("/keywords/:keyword/:format", routing::get(gets_by_keyword))
+        .route("/authors/:id/:format", routing::get(gets_by_author))
+}
@@ -0,0 +1,380 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{debug_handler, routing, Json, Router};
+use futures_util::TryStreamExt;
+use mongodm::bson::{to_bson, DateTime};
+use mongodm::prelude::{Inc, ObjectId, Set};
+use mongodm::{doc, field, ToRepository};
+use serde::Deserialize;
+
+use crate::mongo_entities::invitation::Invitation;
+use crate::mongo_entities::profile::{Profile, ProfileId};
+use crate::mongo_entities::thesis::{Thesis, ThesisId};
+use crate::routes::account::find_own_email;
+use crate::routes::common::auth::{AuthInfo, Permission, Scope};
+use crate::routes::common::{err::AppError, mail};
+use crate::routes::thesis::find_thesis_by_id;
+use crate::state::AppState;
+
+async fn find_invitation_by_id(state: &AppState, id: ObjectId) -> Result<Invitation, AppError> {
+    state
+        .mongo_db
+        .repository::<Invitation>()
+        .find_one(
+            doc! {
+                "_id": id
+            },
+            None,
+        )
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "Invitation with id {} does not exist!",
+            id
+        )))
+}
+
+fn is_author(auth_info: &AuthInfo, thesis: &Thesis) -> Result<bool, AppError> {
+    Ok(thesis.id.owner_id == auth_info.id()? || thesis.author_ids.contains(&auth_info.id()?))
+}
+
+async fn pending(state: &AppState, filter: mongodm::bson::Document) -> Result<u64, AppError> {
+    let res = state
+        .mongo_db
+        .repository::<Invitation>()
+        .count_documents(filter, None)
+        .await?;
+    Ok(res)
+}
+
+/// Moves the invitees at or after `position` of a thesis by `by` places.
+async fn shift(
+    state: &AppState,
+    thesis_id: ObjectId,
+    position: u32,
+    by: i32,
+) -> Result<(), AppError> {
+    state
+        .mongo_db
+        .repository::<Invitation>()
+        .update_many(
+            doc! {
+                field!(thesis_id in Invitation): thesis_id,
+                field!(position in Invitation): {
+                    "$gte": position
+                }
+            },
+            doc! {
+                Inc: {
+                    field!(position in Invitation): by
+                }
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Turns an invitation into an entry of `author_ids`, in the place the inviter chose.
+async fn resolve(state: &AppState, invitation: Invitation, id: ObjectId) -> Result<(), AppError> {
+    if let Some(thesis) = state
+        .mongo_db
+        .repository::<Thesis>()
+        .find_one(
+            doc! {
+                "_id": invitation.thesis_id
+            },
+            None,
+        )
+        .await?
+    {
+        if !thesis.author_ids.contains(&id) {
+            // Invitees still pending before this one hold places that are not in `author_ids`.
+            let ahead = pending(
+                state,
+                doc! {
+                    field!(thesis_id in Invitation): invitation.thesis_id,
+                    field!(position in Invitation): {
+                        "$lt": invitation.position
+                    }
+                },
+            )
+            .await?;
+            let index =
+                (u64::from(invitation.position) - ahead).min(thesis.author_ids.len() as u64);
+            state
+                .mongo_db
+                .repository::<Thesis>()
+                .update_one(
+                    doc! {
+                        "_id": invitation.thesis_id
+                    },
+                    doc! {
+                        "$push": {
+                            field!(author_ids in Thesis): {
+                                "$each": [id],
+                                "$position": i64::try_from(index)?
+                            }
+                        },
+                        Set: {
+                            field!(modified_at in ThesisId): DateTime::now()
+                        }
+                    },
+                    None,
+                )
+                .await?;
+        }
+    }
+    state
+        .mongo_db
+        .repository::<Invitation>()
+        .delete_one(
+            doc! {
+                "_id": invitation._id
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Makes the owner of `email` an author of every thesis they have been invited to.
+pub(crate) async fn resolve_all(
+    state: &AppState,
+    email: &lettre::Address,
+    id: ObjectId,
+) -> Result<(), AppError> {
+    let invitations: Vec<Invitation> = state
+        .mongo_db
+        .repository::<Invitation>()
+        .find(
+            doc! {
+                field!(email in Invitation): to_bson(email)?
+            },
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+    for invitation in invitations {
+        resolve(state, invitation, id).await?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct InviteBody {
+    thesis_id: ObjectId,
+    email: lettre::Address,
+    /// Appended after everyone else when omitted.
+    #[serde(default)]
+    position: Option<u32>,
+}
+
+#[debug_handler]
+async fn post(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Json(body): Json<InviteBody>,
+) -> Result<(StatusCode, Json<ObjectId>), AppError> {
+    let thesis = find_thesis_by_id(&state, body.thesis_id).await?;
+    if !is_author(&auth_info, &thesis)? {
+        return Err(AppError::Forbidden(format!(
+            "You are not an author of thesis {}!",
+            body.thesis_id
+        )));
+    }
+    if let Some(profile) = state
+        .mongo_db
+        .repository::<Profile>()
+        .find_one(
+            doc! {
+                field!(email in ProfileId): to_bson(&body.email)?
+            },
+            None,
+        )
+        .await?
+    {
+        if thesis.author_ids.contains(&profile.public_profile.id._id) {
+            return Err(AppError::Conflict(format!(
+                "{} is already an author of thesis {}!",
+                body.email, body.thesis_id
+            )));
+        }
+    }
+    if pending(
+        &state,
+        doc! {
+            field!(thesis_id in Invitation): body.thesis_id,
+            field!(email in Invitation): to_bson(&body.email)?
+        },
+    )
+    .await?
+        > 0
+    {
+        return Err(AppError::Conflict(format!(
+            "{} has already been invited to thesis {}!",
+            body.email, body.thesis_id
+        )));
+    }
+    let total = u32::try_from(
+        thesis.author_ids.len() as u64
+            + pending(
+                &state,
+                doc! {
+                    field!(thesis_id in Invitation): body.thesis_id
+                },
+            )
+            .await?,
+    )?;
+    let position = body.position.unwrap_or(total);
+    if position > total {
+        return Err(AppError::BadRequest(format!(
+            "Position {} is past the last of {} authors!",
+            position, total
+        )));
+    }
+
+    shift(&state, body.thesis_id, position, 1).await?;
+    let res = state
+        .mongo_db
+        .repository::<Invitation>()
+        .insert_one(
+            Invitation {
+                _id: ObjectId::new(),
+                thesis_id: body.thesis_id,
+                email: body.email.clone(),
+                position,
+                inviter_id: auth_info.id()?,
+                invited_at: chrono::Utc::now(),
+            },
+            None,
+        )
+        .await?
+        .inserted_id
+        .as_object_id()
+        .ok_or(anyhow::anyhow!("Cannot get inserted id!"))?;
+    mail::send(
+        &state,
+        &body.email,
+        "You have been listed as a co-author",
+        format!(
+            "You have been listed as a co-author of \"{}\".\n\nSign up or log in with this email address at {} to take your place among its authors.",
+            thesis.title, state.clt_addr
+        ),
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(res)))
+}
+
+#[debug_handler]
+async fn gets(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Invitation>>, AppError> {
+    let email = find_own_email(&auth_info, &state).await?;
+    let res = state
+        .mongo_db
+        .repository::<Invitation>()
+        .find(
+            doc! {
+                field!(email in Invitation): to_bson(&email)?
+            },
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok(Json(res))
+}
+
+#[debug_handler]
+async fn gets_by_thesis(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<Vec<Invitation>>, AppError> {
+    let thesis = find_thesis_by_id(&state, id).await?;
+    if !(auth_info.permitted(Permission::Auditing, Scope::Global)
+        || is_author(&auth_info, &thesis)?)
+    {
+        return Err(AppError::Forbidden(format!(
+            "You are not an author of thesis {}!",
+            id
+        )));
+    }
+    let res = state
+        .mongo_db
+        .repository::<Invitation>()
+        .find(
+            doc! {
+                field!(thesis_id in Invitation): id
+            },
+            mongodm::prelude::MongoFindOptions::builder()
+                .sort(doc! {
+                    field!(position in Invitation): 1
+                })
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok(Json(res))
+}
+
+#[debug_handler]
+async fn accept(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectId>,
+) -> Result<(), AppError> {
+    let invitation = find_invitation_by_id(&state, id).await?;
+    if invitation.email != find_own_email(&auth_info, &state).await? {
+        return Err(AppError::Forbidden(format!(
+            "Invitation {} is not addressed to you!",
+            id
+        )));
+    }
+    resolve(&state, invitation, auth_info.id()?).await
+}
+
+/// Declined by the invitee or withdrawn by an author.
+#[debug_handler]
+async fn delete(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectId>,
+) -> Result<StatusCode, AppError> {
+    let invitation = find_invitation_by_id(&state, id).await?;
+    // Authors may withdraw through API tokens, but only sessions may speak for the invitee.
+    if !is_author(
+        &auth_info,
+        &find_thesis_by_id(&state, invitation.thesis_id).await?,
+    )? && invitation.email != find_own_email(&auth_info, &state).await?
+    {
+        return Err(AppError::Forbidden(format!(
+            "You can neither decline nor withdraw invitation {}!",
+            id
+        )));
+    }
+
+    state
+        .mongo_db
+        .repository::<Invitation>()
+        .delete_one(
+            doc! {
+                "_id": id
+            },
+            None,
+        )
+        .await?;
+    shift(&state, invitation.thesis_id, invitation.position, -1).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/", routing::post(post).get(gets))
+        .route("/:id", routing::delete(delete))
+        .route("/:id/accept", routing::post(accept))
+        .route("/theses/:id", routing::get(gets_by_thesis))
+}
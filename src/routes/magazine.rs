@@ -1,11 +1,14 @@
 use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::{debug_handler, routing, Json, Router};
+use mongodm::bson::to_bson;
 use mongodm::prelude::{MongoFindOneAndReplaceOptions, MongoReturnDocument, ObjectId};
 use mongodm::{doc, ToRepository};
 
+use crate::mongo_entities::audit::AuditAction;
 use crate::mongo_entities::paper_collection::Magazine;
-use crate::routes::common::auth::{AuthInfo, Permission};
+use crate::routes::common::audit;
+use crate::routes::common::auth::{AuthInfo, Permission, Scope};
 use crate::routes::common::err::AppError;
 use crate::state::AppState;
 
@@ -15,7 +18,7 @@ async fn post(
     State(state): State<AppState>,
     Json(mut body): Json<Magazine>,
 ) -> Result<(StatusCode, Json<ObjectId>), AppError> {
-    if !auth_info.permitted(Permission::Managing) {
+    if !auth_info.permitted(Permission::Managing, Scope::Global) {
         return Err(AppError::Forbidden(
             "You are not a administrator!".to_string(),
         ));
@@ -23,6 +26,7 @@ async fn post(
 
     body.meta._id = ObjectId::new();
     body.modified_at = chrono::Utc::now();
+    let after = to_bson(&body)?;
     let res = state
         .mongo_db
         .repository::<Magazine>()
@@ -31,6 +35,15 @@ async fn post(
         .inserted_id
         .as_object_id()
         .ok_or(anyhow::anyhow!("Cannot get inserted id!"))?;
+    audit::record(
+        &state,
+        auth_info.id()?,
+        AuditAction::MagazineCreated,
+        res,
+        None,
+        Some(after),
+    )
+    .await?;
     Ok((StatusCode::CREATED, Json(res)))
 }
 
@@ -40,6 +53,11 @@ async fn get(
     State(state): State<AppState>,
     Path(id): Path<ObjectId>,
 ) -> Result<Json<Magazine>, AppError> {
+    let res = find_magazine_by_id(&state, id).await?;
+    Ok(Json(res))
+}
+
+async fn find_magazine_by_id(state: &AppState, id: ObjectId) -> Result<Magazine, AppError> {
     let res = state
         .mongo_db
         .repository::<Magazine>()
@@ -54,7 +72,7 @@ async fn get(
             "Magazine with id {} does not exist!",
             id
         )))?;
-    Ok(Json(res))
+    Ok(res)
 }
 
 #[debug_handler]
@@ -64,7 +82,7 @@ async fn put(
     Path(id): Path<ObjectId>,
     Json(mut body): Json<Magazine>,
 ) -> Result<Json<Magazine>, AppError> {
-    if !auth_info.permitted(Permission::Managing) {
+    if !auth_info.permitted(Permission::Managing, Scope::Magazine(id)) {
         return Err(AppError::Forbidden(
             "You are not a administrator!".to_string(),
         ));
@@ -72,7 +90,7 @@ async fn put(
 
     body.meta._id = id;
     body.modified_at = chrono::Utc::now();
-    let res = state
+    let before = state
         .mongo_db
         .repository::<Magazine>()
         .find_one_and_replace(
@@ -81,11 +99,24 @@ async fn put(
             },
             body,
             MongoFindOneAndReplaceOptions::builder()
-                .return_document(MongoReturnDocument::After)
+                .return_document(MongoReturnDocument::Before)
                 .build(),
         )
         .await?
-        .ok_or(anyhow::anyhow!("Cannot get updated thesis!"))?;
+        .ok_or(AppError::NotFound(format!(
+            "Magazine with id {} does not exist!",
+            id
+        )))?;
+    let res = find_magazine_by_id(&state, id).await?;
+    audit::record(
+        &state,
+        auth_info.id()?,
+        AuditAction::MagazineReplaced,
+        id,
+        Some(to_bson(&before)?),
+        Some(to_bson(&res)?),
+    )
+    .await?;
     Ok(Json(res))
 }
 
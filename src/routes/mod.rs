@@ -1,32 +1,49 @@
 use axum::{routing, Router};
 use axum_csrf_sync_pattern::CsrfLayer;
-use axum_sessions::async_session::MemoryStore;
 use axum_sessions::SessionLayer;
-use rand::Rng;
 use utoipa::OpenApi;
 
 use crate::state::AppState;
 
 mod account;
+mod audit_log;
+mod citation;
 mod comment;
-mod common;
+pub(crate) mod common;
+mod crossref;
+mod feed;
 mod file;
+mod invitation;
 mod magazine;
+mod notification;
+mod oai;
 mod review;
-mod thesis;
+pub(crate) mod thesis;
 mod version;
 
 #[derive(OpenApi)]
 #[openapi(components(schemas(crate::mongo_entities::ObjectIdDef)))]
 struct ApiDoc;
 
-pub(crate) fn new() -> Router<AppState> {
+pub(crate) fn new(
+    session_store: common::session::MongoSessionStore,
+    session_secret: &[u8],
+    session_ttl: std::time::Duration,
+) -> Router<AppState> {
     account::new()
         .nest("/magazines", magazine::new())
-        .nest("/theses", thesis::new())
+        .nest(
+            "/theses",
+            thesis::new().merge(crossref::new()).merge(citation::new()),
+        )
         .nest("/versions", version::new())
         .nest("/reviews", review::new())
         .nest("/comments", comment::new())
+        .nest("/invitations", invitation::new())
+        .nest("/audit_logs", audit_log::new())
+        .nest("/notifications", notification::new())
+        .nest("/oai", oai::new())
+        .nest("/feeds", feed::new())
         .nest("/files", file::new())
         .route("/", routing::get(|| async {}))
         .nest(
@@ -34,8 +51,5 @@ pub(crate) fn new() -> Router<AppState> {
             axum_static::static_router("static").with_state(()),
         )
         //.layer(CsrfLayer::new())
-        .layer(SessionLayer::new(
-            MemoryStore::new(),
-            &rand::thread_rng().gen::<[u8; 128]>(),
-        ))
+        .layer(SessionLayer::new(session_store, session_secret).with_session_ttl(Some(session_ttl)))
 }
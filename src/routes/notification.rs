@@ -0,0 +1,166 @@
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::{debug_handler, routing, Json, Router};
+use futures_util::{Stream, TryStreamExt};
+use mongodm::bson::{to_bson, Bson};
+use mongodm::prelude::{MongoFindOptions, ObjectId, Set};
+use mongodm::{doc, field, ToRepository};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::mongo_entities::notification::Notification;
+use crate::routes::common::auth::AuthInfo;
+use crate::routes::common::err::AppError;
+use crate::routes::common::query::AppQuery;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+struct UnreadQuery {
+    #[serde(default)]
+    unread: bool,
+}
+
+#[debug_handler]
+async fn gets(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Query(query): Query<AppQuery>,
+    Query(unread_query): Query<UnreadQuery>,
+) -> Result<([(HeaderName, HeaderValue); 4], Json<Vec<Notification>>), AppError> {
+    let mut filter = doc! {
+        field!(recipient_id in Notification): auth_info.id()?
+    };
+    if unread_query.unread {
+        filter.insert(field!(read_at in Notification), Bson::Null);
+    }
+    let count = state
+        .mongo_db
+        .repository::<Notification>()
+        .count_documents(filter.clone(), None)
+        .await?;
+    let res = state
+        .mongo_db
+        .repository::<Notification>()
+        .find(
+            filter,
+            MongoFindOptions::builder()
+                .sort(doc! {
+                    field!(created_at in Notification): -1
+                })
+                .skip(query.offset)
+                .limit(query.limit)
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok((query.pagenate(count), Json(res)))
+}
+
+#[debug_handler]
+async fn unread(auth_info: AuthInfo, State(state): State<AppState>) -> Result<Json<u64>, AppError> {
+    let res = state
+        .mongo_db
+        .repository::<Notification>()
+        .count_documents(
+            doc! {
+                field!(recipient_id in Notification): auth_info.id()?,
+                field!(read_at in Notification): Bson::Null
+            },
+            None,
+        )
+        .await?;
+    Ok(Json(res))
+}
+
+#[debug_handler]
+async fn read(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectId>,
+) -> Result<StatusCode, AppError> {
+    let res = state
+        .mongo_db
+        .repository::<Notification>()
+        .update_one(
+            doc! {
+                "_id": id,
+                field!(recipient_id in Notification): auth_info.id()?
+            },
+            doc! {
+                Set: {
+                    field!(read_at in Notification): to_bson(&chrono::Utc::now())?
+                }
+            },
+            None,
+        )
+        .await?;
+    if res.matched_count == 0 {
+        return Err(AppError::NotFound(format!(
+            "Notification with id {} does not exist!",
+            id
+        )));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[debug_handler]
+async fn read_all(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+) -> Result<Json<u64>, AppError> {
+    let res = state
+        .mongo_db
+        .repository::<Notification>()
+        .update_many(
+            doc! {
+                field!(recipient_id in Notification): auth_info.id()?,
+                field!(read_at in Notification): Bson::Null
+            },
+            doc! {
+                Set: {
+                    field!(read_at in Notification): to_bson(&chrono::Utc::now())?
+                }
+            },
+            None,
+        )
+        .await?
+        .modified_count;
+    Ok(Json(res))
+}
+
+/// Streams the notifications of the current user as Server-Sent Events named after their kind.
+#[debug_handler]
+async fn stream(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, AppError> {
+    let id = auth_info.id()?;
+    let receiver = state.events.subscribe();
+    let res = futures_util::stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(notification) if notification.recipient_id == id => {
+                    let event = Event::default()
+                        .event(to_bson(&notification.kind).ok()?.as_str()?)
+                        .json_data(notification.as_ref());
+                    return Some((event, receiver));
+                }
+                Ok(_) => {}
+                // The client reloads the list on reconnecting rather than miss some silently.
+                Err(RecvError::Lagged(_)) | Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(res).keep_alive(KeepAlive::default()))
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new()
+        .route("/", routing::get(gets))
+        .route("/stream", routing::get(stream))
+        .route("/unread", routing::get(unread))
+        .route("/read", routing::patch(read_all))
+        .route("/:id/read", routing::patch(read))
+}
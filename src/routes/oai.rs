@@ -0,0 +1,628 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderName, HeaderValue};
+use axum::{debug_handler, routing, Form, Router};
+use base64::Engine;
+use futures_util::TryStreamExt;
+use mongodm::bson::{to_bson, DateTime, Document};
+use mongodm::prelude::{MongoFindOneOptions, MongoFindOptions, ObjectId};
+use mongodm::{doc, field, ToRepository};
+use serde::{Deserialize, Serialize};
+
+use crate::mongo_entities::paper_collection::{Category, Magazine};
+use crate::mongo_entities::thesis::{Thesis, ThesisId};
+use crate::routes::common::err::AppError;
+use crate::routes::common::query;
+use crate::routes::common::xml::escape;
+use crate::routes::thesis::{find_author_names, find_publication_date};
+use crate::state::AppState;
+
+const PAGE_SIZE: u64 = 100;
+const OAI_DC: &str = "oai_dc";
+const DATESTAMP: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// Reported to harvesters inside a normal response, as the protocol demands.
+enum OaiError {
+    Protocol(&'static str, String),
+    App(AppError),
+}
+
+impl OaiError {
+    fn bad_argument(message: impl Into<String>) -> Self {
+        Self::Protocol("badArgument", message.into())
+    }
+
+    /// Whether the request is echoed without its arguments.
+    fn hides_arguments(&self) -> bool {
+        matches!(self, Self::Protocol("badVerb" | "badArgument", _))
+    }
+}
+
+impl<T: Into<AppError>> From<T> for OaiError {
+    fn from(value: T) -> Self {
+        Self::App(value.into())
+    }
+}
+
+type Reply = Result<String, OaiError>;
+
+/// The request arguments, kept as a list so that repeated ones can be refused.
+struct Args(Vec<(String, String)>);
+
+impl Args {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Besides `verb`, `required` must all be given and nothing outside `optional` may be.
+    fn check(&self, required: &[&str], optional: &[&str]) -> Result<(), OaiError> {
+        for (i, (name, _)) in self.0.iter().enumerate() {
+            if self.0[..i].iter().any(|(other, _)| other == name) {
+                return Err(OaiError::bad_argument(format!(
+                    "Argument {} is repeated!",
+                    name
+                )));
+            }
+            if name != "verb"
+                && !required.contains(&name.as_str())
+                && !optional.contains(&name.as_str())
+            {
+                return Err(OaiError::bad_argument(format!(
+                    "Illegal argument {}!",
+                    name
+                )));
+            }
+        }
+        if let Some(name) = required.iter().find(|name| self.get(name).is_none()) {
+            return Err(OaiError::bad_argument(format!(
+                "Missing argument {}!",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    /// The exclusive `resumptionToken` or else the arguments it stands for.
+    fn selection(&self) -> Result<Selection, OaiError> {
+        if let Some(token) = self.get("resumptionToken") {
+            self.check(&["resumptionToken"], &[])?;
+            return Selection::decode(token);
+        }
+        self.check(&["metadataPrefix"], &["from", "until", "set"])?;
+        let metadata_prefix = self.get("metadataPrefix").unwrap_or_default();
+        if metadata_prefix != OAI_DC {
+            return Err(cannot_disseminate(metadata_prefix));
+        }
+        let from = self.get("from").map(parse_datestamp).transpose()?;
+        let until = self.get("until").map(parse_datestamp).transpose()?;
+        if let (Some((from, from_is_day)), Some((until, until_is_day))) = (from, until) {
+            if from_is_day != until_is_day {
+                return Err(OaiError::bad_argument(
+                    "from and until differ in granularity!",
+                ));
+            }
+            if from > until {
+                return Err(OaiError::bad_argument("from is later than until!"));
+            }
+        }
+        Ok(Selection {
+            set: self.get("set").map(ToString::to_string),
+            from: from.map(|(from, _)| from),
+            // Stored datestamps are finer than the granularity a harvester can ask for.
+            until: until.map(|(until, is_day)| {
+                until
+                    + if is_day {
+                        chrono::Duration::days(1)
+                    } else {
+                        chrono::Duration::seconds(1)
+                    }
+            }),
+            offset: 0,
+        })
+    }
+}
+
+fn cannot_disseminate(metadata_prefix: &str) -> OaiError {
+    OaiError::Protocol(
+        "cannotDisseminateFormat",
+        format!("Only {} is supported, not {}!", OAI_DC, metadata_prefix),
+    )
+}
+
+/// Either a day or a second, telling which one it was.
+fn parse_datestamp(datestamp: &str) -> Result<(chrono::DateTime<chrono::Utc>, bool), OaiError> {
+    if let Ok(day) = chrono::NaiveDate::parse_from_str(datestamp, "%Y-%m-%d") {
+        return Ok((day.and_time(chrono::NaiveTime::MIN).and_utc(), true));
+    }
+    chrono::NaiveDateTime::parse_from_str(datestamp, DATESTAMP)
+        .map(|time| (time.and_utc(), false))
+        .map_err(|_| OaiError::bad_argument(format!("Invalid datestamp {}!", datestamp)))
+}
+
+/// Which part of a list is asked for; resumption tokens are this, encoded.
+#[derive(Serialize, Deserialize)]
+struct Selection {
+    set: Option<String>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    /// Exclusive.
+    until: Option<chrono::DateTime<chrono::Utc>>,
+    offset: u64,
+}
+
+impl Selection {
+    fn encode(&self) -> Result<String, OaiError> {
+        Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(self)?))
+    }
+
+    fn decode(token: &str) -> Result<Self, OaiError> {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or(OaiError::Protocol(
+                "badResumptionToken",
+                format!("Invalid resumption token {}!", token),
+            ))
+    }
+}
+
+/// What a request needs to look up beyond the theses themselves.
+struct Repository<'a> {
+    state: &'a AppState,
+    host: String,
+    categories: Vec<Category>,
+    magazines: HashMap<ObjectId, String>,
+}
+
+impl<'a> Repository<'a> {
+    async fn new(state: &'a AppState) -> Result<Repository<'a>, OaiError> {
+        let categories = state
+            .mongo_db
+            .repository::<Category>()
+            .find(
+                doc! {
+                    field!(is_public in Category): true
+                },
+                None,
+            )
+            .await?
+            .try_collect()
+            .await?;
+        let magazines = state
+            .mongo_db
+            .repository::<Magazine>()
+            .find(doc! {}, None)
+            .await?
+            .map_ok(|magazine: Magazine| (magazine.meta._id, magazine.meta.name))
+            .try_collect()
+            .await?;
+        Ok(Self {
+            state,
+            host: state
+                .oai_base_url
+                .host_str()
+                .unwrap_or_default()
+                .to_string(),
+            categories,
+            magazines,
+        })
+    }
+
+    fn identifier(&self, id: ObjectId) -> String {
+        format!("oai:{}:{}", self.host, id)
+    }
+
+    async fn find_thesis(&self, identifier: &str) -> Result<Thesis, OaiError> {
+        let missing = || {
+            OaiError::Protocol(
+                "idDoesNotExist",
+                format!("No record has identifier {}!", identifier),
+            )
+        };
+        let id = identifier
+            .strip_prefix(&format!("oai:{}:", self.host))
+            .and_then(|id| ObjectId::parse_str(id).ok())
+            .ok_or_else(missing)?;
+        self.state
+            .mongo_db
+            .repository::<Thesis>()
+            .find_one(
+                doc! {
+                    "_id": id,
+                    field!(is_passed in ThesisId): true
+                },
+                None,
+            )
+            .await?
+            .ok_or_else(missing)
+    }
+
+    /// `magazine-<id>` covers its theses and `category-<id>` those it lists directly or by magazine;
+    /// a `:` would make them members of `magazine` and `category` sets, which there are not.
+    fn set_specs(&self, thesis: &Thesis) -> Vec<String> {
+        thesis
+            .magazine_id
+            .map(|magazine_id| format!("magazine-{}", magazine_id))
+            .into_iter()
+            .chain(
+                self.categories
+                    .iter()
+                    .filter(|category| {
+                        category.thesis_ids.contains(&thesis.id._id)
+                            || thesis
+                                .magazine_id
+                                .is_some_and(|id| category.magazine_ids.contains(&id))
+                    })
+                    .map(|category| format!("category-{}", category.meta._id)),
+            )
+            .collect()
+    }
+
+    fn filter(&self, selection: &Selection) -> Result<Document, OaiError> {
+        let mut res = doc! {
+            field!(is_passed in ThesisId): true
+        };
+        if let Some(passed_at) = query::between(
+            selection.from.map(DateTime::from_chrono),
+            selection.until.map(DateTime::from_chrono),
+        )? {
+            res.insert(field!(passed_at in ThesisId), passed_at);
+        }
+        if let Some(set) = &selection.set {
+            let no_such_set = || OaiError::bad_argument(format!("Set {} does not exist!", set));
+            let (kind, id) = set.split_once('-').ok_or_else(no_such_set)?;
+            let id = ObjectId::parse_str(id).map_err(|_| no_such_set())?;
+            match kind {
+                "magazine" if self.magazines.contains_key(&id) => {
+                    res.insert(field!(magazine_id in Thesis), id);
+                }
+                "category" => {
+                    let category = self
+                        .categories
+                        .iter()
+                        .find(|category| category.meta._id == id)
+                        .ok_or_else(no_such_set)?;
+                    res.insert(
+                        "$or",
+                        vec![
+                            doc! {
+                                "_id": {
+                                    "$in": to_bson(&category.thesis_ids)?
+                                }
+                            },
+                            doc! {
+                                field!(magazine_id in Thesis): {
+                                    "$in": to_bson(&category.magazine_ids)?
+                                }
+                            },
+                        ],
+                    );
+                }
+                _ => return Err(no_such_set()),
+            }
+        }
+        Ok(res)
+    }
+
+    fn write_header(&self, res: &mut String, thesis: &Thesis) -> std::fmt::Result {
+        write!(
+            res,
+            "<header><identifier>{}</identifier><datestamp>{}</datestamp>",
+            escape(&self.identifier(thesis.id._id)),
+            thesis.last_passed_at().format(DATESTAMP)
+        )?;
+        for set_spec in self.set_specs(thesis) {
+            write!(res, "<setSpec>{}</setSpec>", set_spec)?;
+        }
+        res.push_str("</header>");
+        Ok(())
+    }
+
+    async fn write_record(&self, res: &mut String, thesis: &Thesis) -> Result<(), OaiError> {
+        let authors = find_author_names(self.state, thesis).await?;
+        let published_at = find_publication_date(self.state, thesis.id._id).await?;
+        res.push_str("<record>");
+        self.write_header(res, thesis)?;
+        res.push_str(
+            "<metadata><oai_dc:dc xmlns:oai_dc=\"http://www.openarchives.org/OAI/2.0/oai_dc/\" \
+            xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+            xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+            xsi:schemaLocation=\"http://www.openarchives.org/OAI/2.0/oai_dc/ \
+            http://www.openarchives.org/OAI/2.0/oai_dc.xsd\">",
+        );
+        write!(res, "<dc:title>{}</dc:title>", escape(&thesis.title))?;
+        for author in &authors {
+            write!(res, "<dc:creator>{}</dc:creator>", escape(author))?;
+        }
+        for keyword in &thesis.keywords {
+            write!(res, "<dc:subject>{}</dc:subject>", escape(keyword))?;
+        }
+        if !thesis.abstraction.is_empty() {
+            write!(
+                res,
+                "<dc:description>{}</dc:description>",
+                escape(&thesis.abstraction)
+            )?;
+        }
+        if let Some(magazine) = thesis
+            .magazine_id
+            .and_then(|magazine_id| self.magazines.get(&magazine_id))
+        {
+            write!(res, "<dc:publisher>{}</dc:publisher>", escape(magazine))?;
+        }
+        write!(
+            res,
+            "<dc:date>{}</dc:date><dc:type>Text</dc:type><dc:format>application/pdf</dc:format>\
+            <dc:identifier>{}/theses/{}</dc:identifier>",
+            published_at.format("%Y-%m-%d"),
+            escape(&self.state.clt_addr),
+            thesis.id._id
+        )?;
+        if let Some(doi) = &thesis.doi {
+            write!(
+                res,
+                "<dc:identifier>https://doi.org/{}</dc:identifier>",
+                escape(doi)
+            )?;
+        }
+        for language in &thesis.languages {
+            write!(res, "<dc:language>{}</dc:language>", escape(language))?;
+        }
+        res.push_str("</oai_dc:dc></metadata></record>");
+        Ok(())
+    }
+
+    async fn identify(&self, args: &Args) -> Reply {
+        args.check(&[], &[])?;
+        let earliest = self
+            .state
+            .mongo_db
+            .repository::<Thesis>()
+            .find_one(
+                doc! {
+                    field!(is_passed in ThesisId): true
+                },
+                MongoFindOneOptions::builder()
+                    .sort(doc! {
+                        field!(passed_at in ThesisId): 1
+                    })
+                    .build(),
+            )
+            .await?
+            .map(|thesis| thesis.last_passed_at())
+            .unwrap_or_else(chrono::Utc::now);
+        let mut res = String::new();
+        write!(
+            res,
+            "<Identify><repositoryName>{}</repositoryName><baseURL>{}</baseURL>\
+            <protocolVersion>2.0</protocolVersion><adminEmail>{}</adminEmail>\
+            <earliestDatestamp>{}</earliestDatestamp><deletedRecord>no</deletedRecord>\
+            <granularity>YYYY-MM-DDThh:mm:ssZ</granularity></Identify>",
+            escape(&self.state.oai_repository_name),
+            escape(self.state.oai_base_url.as_str()),
+            escape(self.state.sender.email.as_ref()),
+            earliest.format(DATESTAMP)
+        )
+        .map_err(anyhow::Error::from)?;
+        Ok(res)
+    }
+
+    async fn list_metadata_formats(&self, args: &Args) -> Reply {
+        args.check(&[], &["identifier"])?;
+        if let Some(identifier) = args.get("identifier") {
+            self.find_thesis(identifier).await?;
+        }
+        Ok(format!(
+            "<ListMetadataFormats><metadataFormat><metadataPrefix>{}</metadataPrefix>\
+            <schema>http://www.openarchives.org/OAI/2.0/oai_dc.xsd</schema>\
+            <metadataNamespace>http://www.openarchives.org/OAI/2.0/oai_dc/</metadataNamespace>\
+            </metadataFormat></ListMetadataFormats>",
+            OAI_DC
+        ))
+    }
+
+    /// All sets fit in one response, so no resumption token is ever handed out.
+    async fn list_sets(&self, args: &Args) -> Reply {
+        if let Some(token) = args.get("resumptionToken") {
+            args.check(&["resumptionToken"], &[])?;
+            return Err(OaiError::Protocol(
+                "badResumptionToken",
+                format!("Invalid resumption token {}!", token),
+            ));
+        }
+        args.check(&[], &[])?;
+        let mut res = "<ListSets>".to_string();
+        let mut magazines: Vec<_> = self.magazines.iter().collect();
+        magazines.sort();
+        for (id, name) in magazines {
+            write!(
+                res,
+                "<set><setSpec>magazine-{}</setSpec><setName>{}</setName></set>",
+                id,
+                escape(name)
+            )
+            .map_err(anyhow::Error::from)?;
+        }
+        for category in &self.categories {
+            write!(
+                res,
+                "<set><setSpec>category-{}</setSpec><setName>{}</setName></set>",
+                category.meta._id,
+                escape(&category.meta.name)
+            )
+            .map_err(anyhow::Error::from)?;
+        }
+        res.push_str("</ListSets>");
+        Ok(res)
+    }
+
+    async fn get_record(&self, args: &Args) -> Reply {
+        args.check(&["identifier", "metadataPrefix"], &[])?;
+        let thesis = self
+            .find_thesis(args.get("identifier").unwrap_or_default())
+            .await?;
+        let metadata_prefix = args.get("metadataPrefix").unwrap_or_default();
+        if metadata_prefix != OAI_DC {
+            return Err(cannot_disseminate(metadata_prefix));
+        }
+        let mut res = "<GetRecord>".to_string();
+        self.write_record(&mut res, &thesis).await?;
+        res.push_str("</GetRecord>");
+        Ok(res)
+    }
+
+    /// `ListIdentifiers` and `ListRecords` differ only in whether metadata comes along.
+    async fn list(&self, args: &Args, verb: &str, with_metadata: bool) -> Reply {
+        let selection = args.selection()?;
+        let filter = self.filter(&selection)?;
+        let count = self
+            .state
+            .mongo_db
+            .repository::<Thesis>()
+            .count_documents(filter.clone(), None)
+            .await?;
+        if count == 0 || selection.offset >= count {
+            return Err(OaiError::Protocol(
+                "noRecordsMatch",
+                "No record matches the request!".to_string(),
+            ));
+        }
+        let theses: Vec<Thesis> = self
+            .state
+            .mongo_db
+            .repository::<Thesis>()
+            .find(
+                filter,
+                MongoFindOptions::builder()
+                    .sort(doc! {
+                        field!(passed_at in ThesisId): 1,
+                        "_id": 1
+                    })
+                    .skip(selection.offset)
+                    .limit(i64::try_from(PAGE_SIZE)?)
+                    .build(),
+            )
+            .await?
+            .try_collect()
+            .await?;
+
+        let mut res = format!("<{}>", verb);
+        for thesis in &theses {
+            if with_metadata {
+                self.write_record(&mut res, thesis).await?;
+            } else {
+                self.write_header(&mut res, thesis)
+                    .map_err(anyhow::Error::from)?;
+            }
+        }
+        let next = selection.offset + theses.len() as u64;
+        if next < count {
+            write!(
+                res,
+                "<resumptionToken completeListSize=\"{}\" cursor=\"{}\">{}</resumptionToken>",
+                count,
+                selection.offset,
+                Selection {
+                    offset: next,
+                    ..selection
+                }
+                .encode()?
+            )
+            .map_err(anyhow::Error::from)?;
+        } else if selection.offset > 0 {
+            write!(
+                res,
+                "<resumptionToken completeListSize=\"{}\" cursor=\"{}\"/>",
+                count, selection.offset
+            )
+            .map_err(anyhow::Error::from)?;
+        }
+        write!(res, "</{}>", verb).map_err(anyhow::Error::from)?;
+        Ok(res)
+    }
+}
+
+async fn respond(
+    state: &AppState,
+    args: Vec<(String, String)>,
+) -> Result<([(HeaderName, HeaderValue); 1], String), AppError> {
+    let args = Args(args);
+    let repository = Repository::new(state).await.map_err(|err| match err {
+        OaiError::App(err) => err,
+        OaiError::Protocol(code, message) => anyhow::anyhow!("{}: {}", code, message).into(),
+    })?;
+    let verb = args.get("verb").unwrap_or_default();
+    let reply = match verb {
+        "Identify" => repository.identify(&args).await,
+        "ListMetadataFormats" => repository.list_metadata_formats(&args).await,
+        "ListSets" => repository.list_sets(&args).await,
+        "GetRecord" => repository.get_record(&args).await,
+        "ListIdentifiers" => repository.list(&args, verb, false).await,
+        "ListRecords" => repository.list(&args, verb, true).await,
+        _ => Err(OaiError::Protocol(
+            "badVerb",
+            format!("Illegal verb {:?}!", verb),
+        )),
+    };
+
+    let mut res = String::new();
+    write!(
+        res,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <OAI-PMH xmlns=\"http://www.openarchives.org/OAI/2.0/\" \
+        xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+        xsi:schemaLocation=\"http://www.openarchives.org/OAI/2.0/ \
+        http://www.openarchives.org/OAI/2.0/OAI-PMH.xsd\">\
+        <responseDate>{}</responseDate><request",
+        chrono::Utc::now().format(DATESTAMP)
+    )
+    .map_err(anyhow::Error::from)?;
+    if !matches!(&reply, Err(err) if err.hides_arguments()) {
+        for (name, value) in &args.0 {
+            write!(res, " {}=\"{}\"", name, escape(value)).map_err(anyhow::Error::from)?;
+        }
+    }
+    write!(res, ">{}</request>", escape(state.oai_base_url.as_str()))
+        .map_err(anyhow::Error::from)?;
+    match reply {
+        Ok(reply) => res.push_str(&reply),
+        Err(OaiError::Protocol(code, message)) => {
+            write!(res, "<error code=\"{}\">{}</error>", code, escape(&message))
+                .map_err(anyhow::Error::from)?;
+        }
+        Err(OaiError::App(err)) => return Err(err),
+    }
+    res.push_str("</OAI-PMH>");
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/xml; charset=utf-8"),
+        )],
+        res,
+    ))
+}
+
+#[debug_handler]
+async fn get(
+    State(state): State<AppState>,
+    Query(args): Query<Vec<(String, String)>>,
+) -> Result<([(HeaderName, HeaderValue); 1], String), AppError> {
+    respond(&state, args).await
+}
+
+#[debug_handler]
+async fn post(
+    State(state): State<AppState>,
+    Form(args): Form<Vec<(String, String)>>,
+) -> Result<([(HeaderName, HeaderValue); 1], String), AppError> {
+    respond(&state, args).await
+}
+
+pub(super) fn new() -> Router<AppState> {
+    Router::new().route("/", routing::get(get).post(post))
+}
@@ -1,22 +1,26 @@
 use axum::extract::multipart::Field;
-use axum::extract::{Multipart, Path, Query, State};
-use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::extract::{Multipart, OriginalUri, Path, Query, State};
+use axum::http::{HeaderName, HeaderValue, StatusCode, Uri};
 use axum::{debug_handler, routing, Json, Router};
-use futures_util::{Stream, TryStreamExt};
-use mongodm::bson::Document;
+use futures_util::{AsyncReadExt, Stream, TryStreamExt};
+use mongodm::bson::{to_bson, to_document, DateTime, Document};
 use mongodm::mongo::options::GridFsUploadOptions;
 use mongodm::mongo::GridFsBucket;
 use mongodm::prelude::{
-    MongoFindOneAndReplaceOptions, MongoFindOneOptions, MongoFindOptions, MongoReturnDocument,
-    ObjectId,
+    MongoFindOneAndUpdateOptions, MongoFindOneOptions, MongoFindOptions, MongoReturnDocument,
+    ObjectId, Set,
 };
 use mongodm::{doc, field, ToRepository};
+use serde::{Deserialize, Serialize};
 
+use crate::mongo_entities::audit::AuditAction;
 use crate::mongo_entities::paper_collection::Magazine;
+use crate::mongo_entities::profile::Profile;
 use crate::mongo_entities::thesis::{ReviewState, Thesis, ThesisId, Version, VersionState};
-use crate::routes::common::auth::{AuthInfo, Permission};
+use crate::routes::common::audit;
+use crate::routes::common::auth::{AuthInfo, Permission, Scope};
 use crate::routes::common::err::AppError;
-use crate::routes::common::query::AppQuery;
+use crate::routes::common::query::{self, AppQuery, KeysetQuery, Links, Order, Page};
 use crate::state::AppState;
 
 #[debug_handler]
@@ -25,29 +29,18 @@ async fn post(
     State(state): State<AppState>,
     Json(mut body): Json<Thesis>,
 ) -> Result<(StatusCode, Json<ObjectId>), AppError> {
-    // if state
-    //     .mongo_db
-    //     .repository::<Magazine>()
-    //     .find_one(
-    //         doc! {
-    //             "_id": body.magazine_id
-    //         },
-    //         None,
-    //     )
-    //     .await?
-    //     .is_none()
-    // {
-    //     return Err(AppError::BadRequest(format!(
-    //         "No such magazine {}!",
-    //         body.magazine_id
-    //     )));
-    // }
+    check_magazine(&state, &body).await?;
+    let id = ObjectId::new();
+    check_doi(&state, &mut body, id).await?;
 
+    body.text_language = body.stemming_language();
     body.id = ThesisId {
-        _id: ObjectId::new(),
+        _id: id,
         owner_id: auth_info.id()?,
         is_passed: false,
         created_at: chrono::Utc::now(),
+        passed_at: None,
+        modified_at: None,
     };
     let res = state
         .mongo_db
@@ -67,11 +60,50 @@ async fn get(
     Path(id): Path<ObjectId>,
 ) -> Result<Json<Thesis>, AppError> {
     let res = find_thesis_by_id(&state, id).await?;
-    if !(auth_info.permitted(Permission::Publishing)
-        || res.id.owner_id == auth_info.id()?
-        || res.author_ids.contains(&auth_info.id()?))
+    check_visible(&auth_info, &state, &res).await?;
+    Ok(Json(res))
+}
+
+/// Resolves a DOI in any of its usual spellings, e.g. `doi:10.1000/XYZ` or a `doi.org` link.
+#[debug_handler]
+async fn get_by_doi(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(doi): Path<String>,
+) -> Result<Json<Thesis>, AppError> {
+    let doi = normalise_doi(doi.trim_start_matches('/'))?;
+    let res = state
+        .mongo_db
+        .repository::<Thesis>()
+        .find_one(
+            doc! {
+                field!(doi in Thesis): &doi
+            },
+            None,
+        )
+        .await?
+        .ok_or(AppError::NotFound(format!(
+            "Thesis with DOI {} does not exist!",
+            doi
+        )))?;
+    check_visible(&auth_info, &state, &res).await?;
+    Ok(Json(res))
+}
+
+/// Unpublished theses are only shown to their authors, auditors and assigned reviewers.
+async fn check_visible(
+    auth_info: &AuthInfo,
+    state: &AppState,
+    thesis: &Thesis,
+) -> Result<(), AppError> {
+    let id = thesis.id._id;
+    if !(auth_info
+        .permitted_on(state, Permission::Auditing, thesis)
+        .await?
+        || thesis.id.owner_id == auth_info.id()?
+        || thesis.author_ids.contains(&auth_info.id()?))
     {
-        match find_last_version(&state, id).await? {
+        match find_last_version(state, id).await? {
             Some(version) if version.major_num > 0 => {}
             Some(Version {
                 state: VersionState::Reviewing,
@@ -94,28 +126,262 @@ async fn get(
             }
         }
     }
-    Ok(Json(res))
+    Ok(())
+}
+
+const DOI_RESOLVERS: [&str; 5] = [
+    "https://doi.org/",
+    "http://doi.org/",
+    "https://dx.doi.org/",
+    "http://dx.doi.org/",
+    "doi:",
+];
+
+/// Lower-cased, as DOIs are case-insensitive, and without any resolver in front.
+pub(crate) fn normalise_doi(doi: &str) -> Result<String, AppError> {
+    let mut res = doi.trim().to_lowercase();
+    if let Some(resolver) = DOI_RESOLVERS
+        .iter()
+        .find(|resolver| res.starts_with(*resolver))
+    {
+        res = res[resolver.len()..].trim_start().to_string();
+    }
+    let valid = match res.split_once('/') {
+        Some((prefix, suffix)) => match prefix.strip_prefix("10.") {
+            Some(registrant) => {
+                registrant.len() >= 4
+                    && registrant
+                        .split('.')
+                        .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+                    && !suffix.is_empty()
+                    && !suffix.chars().any(|c| c.is_whitespace() || c.is_control())
+            }
+            None => false,
+        },
+        None => false,
+    };
+    if !valid {
+        return Err(AppError::BadRequest(format!("Invalid DOI {}!", doi)));
+    }
+    Ok(res)
+}
+
+/// Normalises the DOI of `thesis` in place and makes sure no other thesis has claimed it.
+async fn check_doi(state: &AppState, thesis: &mut Thesis, id: ObjectId) -> Result<(), AppError> {
+    let Some(doi) = &thesis.doi else {
+        return Ok(());
+    };
+    let doi = normalise_doi(doi)?;
+    if let Some(other) = state
+        .mongo_db
+        .repository::<Thesis>()
+        .find_one(
+            doc! {
+                field!(doi in Thesis): &doi,
+                "_id": {
+                    "$ne": id
+                }
+            },
+            None,
+        )
+        .await?
+    {
+        return Err(AppError::Conflict(format!(
+            "DOI {} has been taken by thesis {}!",
+            doi, other.id._id
+        )));
+    }
+    thesis.doi = Some(doi);
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[derive(Default)]
+#[serde(rename_all = "snake_case")]
+enum ThesisSort {
+    #[default]
+    CreatedAt,
+    Title,
+}
+
+/// The only filters `gets` understands; lists are comma-separated and match any of their items.
+#[derive(Deserialize)]
+pub(super) struct ThesisQuery {
+    #[serde(default)]
+    owner_id: Option<ObjectId>,
+    #[serde(default)]
+    magazine_id: Option<ObjectId>,
+    #[serde(default)]
+    doi: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    keywords: Option<String>,
+    #[serde(default)]
+    languages: Option<String>,
+    #[serde(default)]
+    author_ids: Option<String>,
+    #[serde(default)]
+    created_since: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    created_until: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    sort: ThesisSort,
+    #[serde(default)]
+    pub(super) order: Order,
+}
+
+impl ThesisQuery {
+    pub(super) fn filter(&self) -> Result<Document, AppError> {
+        let mut res = doc! {
+            field!(is_passed in ThesisId): true
+        };
+        if let Some(owner_id) = self.owner_id {
+            res.insert(field!(owner_id in ThesisId), owner_id);
+        }
+        if let Some(magazine_id) = self.magazine_id {
+            res.insert(field!(magazine_id in Thesis), magazine_id);
+        }
+        if let Some(doi) = &self.doi {
+            res.insert(field!(doi in Thesis), normalise_doi(doi)?);
+        }
+        if let Some(title) = &self.title {
+            res.insert(field!(title in Thesis), title);
+        }
+        if let Some(keywords) = &self.keywords {
+            res.insert(
+                field!(keywords in Thesis),
+                query::one_of::<String>("keywords", keywords)?,
+            );
+        }
+        if let Some(languages) = &self.languages {
+            res.insert(
+                field!(languages in Thesis),
+                query::one_of::<String>("languages", languages)?,
+            );
+        }
+        if let Some(author_ids) = &self.author_ids {
+            res.insert(
+                field!(author_ids in Thesis),
+                query::one_of::<ObjectId>("author_ids", author_ids)?,
+            );
+        }
+        if let Some(created_at) = query::between(self.created_since, self.created_until)? {
+            res.insert(field!(created_at in ThesisId), created_at);
+        }
+        Ok(res)
+    }
+
+    pub(super) fn sort_key(&self) -> &'static str {
+        match self.sort {
+            ThesisSort::CreatedAt => field!(created_at in ThesisId),
+            ThesisSort::Title => field!(title in Thesis),
+        }
+    }
 }
 
 #[debug_handler]
 async fn gets(
     _auth_info: AuthInfo,
     State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
     Query(query): Query<AppQuery>,
-    Json(mut body): Json<Document>,
-) -> Result<([(HeaderName, HeaderValue); 4], Json<Vec<Thesis>>), AppError> {
-    body.insert(field!(is_passed in ThesisId), true);
+    Query(keyset): Query<KeysetQuery>,
+    Query(thesis_query): Query<ThesisQuery>,
+) -> Result<([(HeaderName, HeaderValue); 4], Links, Json<Vec<Thesis>>), AppError> {
+    let (count, links, res) = list(&state, &uri, &query, &keyset, &thesis_query).await?;
+    Ok((query.pagenate(count), links, Json(res)))
+}
+
+/// The total count, the page links and one page of the theses `thesis_query` selects.
+pub(super) async fn list(
+    state: &AppState,
+    uri: &Uri,
+    query: &AppQuery,
+    keyset: &KeysetQuery,
+    thesis_query: &ThesisQuery,
+) -> Result<(u64, Links, Vec<Thesis>), AppError> {
+    let filter = thesis_query.filter()?;
+    let page = Page::new(keyset, thesis_query.sort_key(), thesis_query.order)?;
     let count = state
         .mongo_db
         .repository::<Thesis>()
-        .count_documents(body.clone(), None)
+        .count_documents(filter.clone(), None)
         .await?;
+    let mut res: Vec<Thesis> = state
+        .mongo_db
+        .repository::<Thesis>()
+        .find(
+            page.filter(filter),
+            MongoFindOptions::builder()
+                .sort(page.sort())
+                .skip(page.skip(query))
+                .limit(page.limit(query))
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    let links = page.finish(query, uri, &mut res)?;
+    Ok((count, links, res))
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    /// Words are matched stemmed and in any order; `"quoted phrases"` must appear verbatim
+    /// and `-words` must not appear at all.
+    q: String,
+    /// Only theses in this language, whose stemming the query then follows.
+    #[serde(default)]
+    language: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchHit {
+    #[serde(flatten)]
+    thesis: Thesis,
+    score: f64,
+}
+
+#[debug_handler]
+async fn search(
+    _auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Query(query): Query<AppQuery>,
+    Query(search): Query<SearchQuery>,
+) -> Result<([(HeaderName, HeaderValue); 4], Json<Vec<SearchHit>>), AppError> {
+    let mut text = doc! {
+        "$search": &search.q
+    };
+    let mut filter = doc! {
+        field!(is_passed in ThesisId): true
+    };
+    if let Some(language) = search.language {
+        let stemming = Thesis::stemming_language_of(std::iter::once(&language));
+        text.insert("$language", stemming);
+        filter.insert(field!(languages in Thesis), language);
+    }
+    filter.insert("$text", text);
+    let count = state
+        .mongo_db
+        .repository::<Thesis>()
+        .count_documents(filter.clone(), None)
+        .await?;
+    let score = doc! {
+        "score": {
+            "$meta": "textScore"
+        }
+    };
     let res = state
         .mongo_db
         .repository::<Thesis>()
+        .get_underlying()
+        .clone_with_type::<SearchHit>()
         .find(
-            body,
+            filter,
             MongoFindOptions::builder()
+                .projection(score.clone())
+                .sort(score)
                 .skip(query.offset)
                 .limit(query.limit)
                 .build(),
@@ -134,34 +400,107 @@ async fn put(
     Json(mut body): Json<Thesis>,
 ) -> Result<Json<Thesis>, AppError> {
     let thesis = find_thesis_by_id(&state, id).await?;
-    if auth_info.permitted(Permission::Publishing)
-        || thesis.id.owner_id == auth_info.id()?
-        || thesis.author_ids.contains(&auth_info.id()?)
+    let is_author =
+        thesis.id.owner_id == auth_info.id()? || thesis.author_ids.contains(&auth_info.id()?);
+    if !(is_author
+        || auth_info
+            .permitted_on(&state, Permission::Publishing, &thesis)
+            .await?)
     {
         return Err(AppError::Forbidden(format!(
             "You are neither an editor or an author of thesis {}!",
             id
         )));
     }
+    // Theses from before magazines were required may stay without one, or be given one by
+    // their authors; once in a magazine, they keep having one.
+    if body.magazine_id.is_some() || thesis.magazine_id.is_some() {
+        let magazine_id = check_magazine(&state, &body).await?;
+        // Authors cannot move a thesis, and editors only into magazines they edit.
+        if thesis
+            .magazine_id
+            .is_some_and(|current| current != magazine_id)
+            && !auth_info.permitted(Permission::Publishing, Scope::Magazine(magazine_id))
+        {
+            return Err(AppError::Forbidden(format!(
+                "You are not an editor of magazine {}!",
+                magazine_id
+            )));
+        }
+    }
+    check_doi(&state, &mut body, id).await?;
 
-    body.id = thesis.id;
+    let before = to_bson(&thesis)?;
+    body.text_language = body.stemming_language();
+    // The fields of `ThesisId` stay as stored, its dates as BSON dates.
+    let mut update = to_document(&body)?;
+    for key in to_document(&thesis.id)?.keys() {
+        update.remove(key);
+    }
+    update.insert(field!(modified_at in ThesisId), DateTime::now());
     let res = state
         .mongo_db
         .repository::<Thesis>()
-        .find_one_and_replace(
+        .find_one_and_update(
             doc! {"_id": id},
-            body,
+            doc! {
+                Set: update
+            },
             Some(
-                MongoFindOneAndReplaceOptions::builder()
+                MongoFindOneAndUpdateOptions::builder()
                     .return_document(Some(MongoReturnDocument::After))
                     .build(),
             ),
         )
         .await?
         .ok_or(anyhow::anyhow!("Cannot get updated thesis!"))?;
+    if !is_author {
+        audit::record(
+            &state,
+            auth_info.id()?,
+            AuditAction::ThesisReplaced,
+            id,
+            Some(before),
+            Some(to_bson(&res)?),
+        )
+        .await?;
+    }
     Ok(Json(res))
 }
 
+pub(super) async fn find_magazine(state: &AppState, id: ObjectId) -> Result<Magazine, AppError> {
+    state
+        .mongo_db
+        .repository::<Magazine>()
+        .find_one(
+            doc! {
+                "_id": id
+            },
+            None,
+        )
+        .await?
+        .ok_or(AppError::BadRequest(format!("No such magazine {}!", id)))
+}
+
+/// A thesis can only target an existing magazine that publishes in all of its languages.
+async fn check_magazine(state: &AppState, thesis: &Thesis) -> Result<ObjectId, AppError> {
+    let magazine_id = thesis
+        .magazine_id
+        .ok_or(AppError::BadRequest("No magazine!".to_string()))?;
+    let magazine = find_magazine(state, magazine_id).await?;
+    let unaccepted: Vec<&String> = thesis
+        .languages
+        .difference(&magazine.meta.languages)
+        .collect();
+    if !unaccepted.is_empty() {
+        return Err(AppError::BadRequest(format!(
+            "Magazine {} does not accept languages {:?}!",
+            magazine_id, unaccepted
+        )));
+    }
+    Ok(magazine_id)
+}
+
 pub(super) async fn find_thesis_by_id(state: &AppState, id: ObjectId) -> Result<Thesis, AppError> {
     let res = state
         .mongo_db
@@ -180,6 +519,87 @@ pub(super) async fn find_thesis_by_id(state: &AppState, id: ObjectId) -> Result<
     Ok(res)
 }
 
+/// Authors in the order they are credited, the owner first unless listed elsewhere.
+pub(super) fn credited_ids(thesis: &Thesis) -> Vec<ObjectId> {
+    let mut res = thesis.author_ids.clone();
+    if !res.contains(&thesis.id.owner_id) {
+        res.insert(0, thesis.id.owner_id);
+    }
+    res
+}
+
+/// Names in the order they are credited.
+pub(super) async fn find_author_names(
+    state: &AppState,
+    thesis: &Thesis,
+) -> Result<Vec<String>, AppError> {
+    let ids = credited_ids(thesis);
+    let profiles: Vec<Profile> = state
+        .mongo_db
+        .repository::<Profile>()
+        .find(
+            doc! {
+                "_id": {
+                    "$in": &ids
+                }
+            },
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+    // Authors who have erased their accounts are no longer credited.
+    let res = ids
+        .iter()
+        .filter_map(|id| {
+            profiles
+                .iter()
+                .find(|profile| profile.public_profile.id._id == *id)
+                .map(|profile| profile.public_profile.name.clone())
+        })
+        .collect();
+    Ok(res)
+}
+
+/// When the thesis first passed; versions that passed before `passed_at` was recorded give their upload time.
+pub(super) async fn find_publication_date(
+    state: &AppState,
+    id: ObjectId,
+) -> Result<chrono::DateTime<chrono::Utc>, AppError> {
+    let version = state
+        .mongo_db
+        .repository::<Version>()
+        .find_one(
+            doc! {
+                field!(thesis_id in Version): id,
+                field!(major_num in Version): {
+                    "$gt": 0
+                }
+            },
+            Some(
+                MongoFindOneOptions::builder()
+                    .sort(doc! {
+                        field!(major_num in Version): 1
+                    })
+                    .build(),
+            ),
+        )
+        .await?
+        .ok_or(AppError::BadRequest(format!(
+            "Thesis {} has not passed yet!",
+            id
+        )))?;
+    Ok(version.passed_at.unwrap_or(version.uploaded_at))
+}
+
+/// Given names and surname, taking the last word of `name` as the surname.
+pub(super) fn split_name(name: &str) -> (Option<&str>, &str) {
+    match name.trim().rsplit_once(char::is_whitespace) {
+        Some((given_name, surname)) => (Some(given_name.trim()), surname),
+        None => (None, name.trim()),
+    }
+}
+
 async fn find_last_version(state: &AppState, id: ObjectId) -> Result<Option<Version>, AppError> {
     let res = state
         .mongo_db
@@ -208,7 +628,10 @@ async fn delete(
     Path(id): Path<ObjectId>,
 ) -> Result<(StatusCode, Json<u64>), AppError> {
     let thesis = find_thesis_by_id(&state, id).await?;
-    if !auth_info.permitted(Permission::Publishing) {
+    let is_editor = auth_info
+        .permitted_on(&state, Permission::Publishing, &thesis)
+        .await?;
+    if !is_editor {
         if thesis.id.owner_id != auth_info.id()? {
             return Err(AppError::Forbidden(format!(
                 "You do not own thesis {}!",
@@ -228,7 +651,22 @@ async fn delete(
         }
     }
 
-    let res = thesis.withdraw_all(state.mongo_db).await?.deleted_count;
+    let before = to_bson(&thesis)?;
+    let res = thesis
+        .withdraw_all(state.mongo_db.clone())
+        .await?
+        .deleted_count;
+    if is_editor {
+        audit::record(
+            &state,
+            auth_info.id()?,
+            AuditAction::ThesisWithdrawn,
+            id,
+            Some(before),
+            None,
+        )
+        .await?;
+    }
     Ok((StatusCode::NO_CONTENT, Json(res)))
 }
 
@@ -265,6 +703,30 @@ async fn upload<'a, 'b>(bucket: &'b GridFsBucket, field: Field<'a>) -> Result<Ob
     Ok(res)
 }
 
+/// `None` when the stored file cannot be parsed as a PDF.
+async fn count_pages(bucket: &GridFsBucket, id: ObjectId) -> Result<Option<usize>, AppError> {
+    let mut content = Vec::new();
+    bucket
+        .open_download_stream(id.into())
+        .await?
+        .read_to_end(&mut content)
+        .await?;
+    let res = tokio::task::spawn_blocking(move || {
+        lopdf::Document::load_mem(&content)
+            .ok()
+            .map(|document| document.get_pages().len())
+    })
+    .await?;
+    Ok(res)
+}
+
+async fn delete_files(bucket: &GridFsBucket, ids: impl IntoIterator<Item = ObjectId>) {
+    for id in ids {
+        // Nothing refers to them yet, so a leftover chunk is harmless.
+        let _ = bucket.delete(id.into()).await;
+    }
+}
+
 #[debug_handler]
 async fn commit(
     auth_info: AuthInfo,
@@ -311,6 +773,28 @@ async fn commit(
             return Err(AppError::BadRequest("No release file!".to_string()));
         };
 
+    let pages_min = match thesis.magazine_id {
+        Some(magazine_id) => find_magazine(&state, magazine_id).await?.pages_min,
+        None => 0,
+    };
+    let pages_min = usize::try_from(pages_min).unwrap_or_default();
+    match count_pages(&bucket, file_id).await? {
+        Some(pages) if pages >= pages_min => {}
+        Some(pages) => {
+            delete_files(&bucket, std::iter::once(file_id).chain(source_id)).await;
+            return Err(AppError::BadRequest(format!(
+                "Release file has {} pages but its magazine requires at least {}!",
+                pages, pages_min
+            )));
+        }
+        None => {
+            delete_files(&bucket, std::iter::once(file_id).chain(source_id)).await;
+            return Err(AppError::BadRequest(
+                "Release file is not a valid PDF!".to_string(),
+            ));
+        }
+    }
+
     let version = Version {
         thesis_id: id,
         uploaded_at: chrono::Utc::now(),
@@ -334,6 +818,8 @@ async fn commit(
 pub(super) fn new() -> Router<AppState> {
     Router::new()
         .route("/", routing::post(post).get(gets))
+        .route("/search", routing::get(search))
+        .route("/doi/*doi", routing::get(get_by_doi))
         .route("/:id", routing::get(get).put(put).delete(delete))
         .route("/:id/commit", routing::post(commit))
 }
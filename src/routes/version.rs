@@ -1,19 +1,27 @@
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderName, HeaderValue, StatusCode};
 use axum::{debug_handler, routing, Json, Router};
+use futures_util::TryStreamExt;
 use mongodm::bson::to_document;
-use mongodm::prelude::{to_bson, MongoFindOneAndUpdateOptions, MongoReturnDocument, ObjectId};
+use mongodm::prelude::{
+    to_bson, MongoFindOneAndUpdateOptions, MongoFindOptions, MongoReturnDocument, ObjectId,
+};
 use mongodm::{
     doc, field,
     prelude::{Pull, Set},
     ToRepository,
 };
+use serde::Deserialize;
 
+use crate::mongo_entities::audit::AuditAction;
+use crate::mongo_entities::notification::NotificationKind;
 use crate::mongo_entities::thesis::{
-    Comment, CommentTargetType, Review, ReviewPattern, ReviewState, Version, VersionState,
+    Comment, CommentTargetType, Review, ReviewPattern, ReviewState, Thesis, Version, VersionState,
 };
-use crate::routes::common::auth::{AuthInfo, Permission};
+use crate::routes::common::auth::{AuthInfo, Permission, Scope};
 use crate::routes::common::err::AppError;
+use crate::routes::common::query::AppQuery;
+use crate::routes::common::{audit, notify};
 use crate::state::AppState;
 
 pub(super) async fn find_version_by_id(
@@ -45,7 +53,9 @@ async fn get(
 ) -> Result<Json<Version>, AppError> {
     let res = find_version_by_id(&state, id).await?;
     let thesis = super::thesis::find_thesis_by_id(&state, res.thesis_id).await?;
-    if !(auth_info.permitted(Permission::Publishing)
+    if !(auth_info
+        .permitted_on(&state, Permission::Auditing, &thesis)
+        .await?
         || res.uploader_id == Some(auth_info.id()?)
         || thesis.id.owner_id == auth_info.id()?
         || thesis.author_ids.contains(&auth_info.id()?))
@@ -164,10 +174,14 @@ async fn edit(
     Path(id): Path<ObjectId>,
     Json(mut body): Json<ReviewState>,
 ) -> Result<Json<Version>, AppError> {
-    if !auth_info.permitted(Permission::Publishing) {
+    let version = find_version_by_id(&state, id).await?;
+    let thesis = super::thesis::find_thesis_by_id(&state, version.thesis_id).await?;
+    if !auth_info
+        .permitted_on(&state, Permission::Publishing, &thesis)
+        .await?
+    {
         return Err(AppError::Forbidden("you are not a editor".to_string()));
     }
-    let version = find_version_by_id(&state, id).await?;
     match version.state {
         VersionState::Uploaded => {}
         _ => {
@@ -200,6 +214,30 @@ async fn edit(
         )
         .await?
         .ok_or(anyhow::anyhow!("Cannot get updated id!"))?;
+    audit::record(
+        &state,
+        auth_info.id()?,
+        AuditAction::VersionEdited,
+        id,
+        Some(to_bson(&version)?),
+        Some(to_bson(&res)?),
+    )
+    .await?;
+    // Reviewers who were assigned already have been told.
+    let assigned = &version.review_state.remainder_reviewer_ids;
+    notify::notify(
+        &state,
+        auth_info.id()?,
+        res.review_state
+            .remainder_reviewer_ids
+            .iter()
+            .copied()
+            .filter(|reviewer_id| !assigned.contains(reviewer_id)),
+        NotificationKind::ReviewerAssigned,
+        id,
+        format!("You have been asked to review \"{}\".", thesis.title),
+    )
+    .await?;
     Ok(Json(res))
 }
 
@@ -249,6 +287,16 @@ async fn review(
         .inserted_id
         .as_object_id()
         .ok_or(anyhow::anyhow!("Cannot get inserted id!"))?;
+    let thesis = super::thesis::find_thesis_by_id(&state, version.thesis_id).await?;
+    notify::notify(
+        &state,
+        auth_info.id()?,
+        notify::authors(&thesis),
+        NotificationKind::ReviewSubmitted,
+        id,
+        format!("A review of \"{}\" has been submitted.", thesis.title),
+    )
+    .await?;
 
     let version = state.mongo_db.repository::<Version>()
         .find_one_and_update(
@@ -264,7 +312,7 @@ async fn review(
         )
         .await?
         .ok_or(anyhow::anyhow!("Cannot get updated id!"))?;
-    match &version.review_state {
+    let version = match &version.review_state {
         ReviewState {
             remainder_reviewer_ids,
             pattern: ReviewPattern::Reviewer,
@@ -281,25 +329,56 @@ async fn review(
             pass
         } =>
         {
-            version.pass(state.mongo_db).await
+            version.pass(state.mongo_db.clone()).await
         }
-        _ => version.reject(state.mongo_db).await,
+        _ => version.reject(state.mongo_db.clone()).await,
     }?
     .ok_or(anyhow::anyhow!("Cannot get updated id!"))?;
+    // Authors hear about the verdict once, when the last assigned reviewer is done.
+    if version.review_state.remainder_reviewer_ids.is_empty() {
+        notify_verdict(&state, auth_info.id()?, &version).await?;
+    }
 
     Ok((StatusCode::CREATED, Json(res)))
 }
 
+async fn notify_verdict(
+    state: &AppState,
+    actor_id: ObjectId,
+    version: &Version,
+) -> Result<(), AppError> {
+    let thesis = super::thesis::find_thesis_by_id(state, version.thesis_id).await?;
+    let (kind, verdict) = match version.state {
+        VersionState::Passed(true) => (NotificationKind::VersionPassed, "accepted"),
+        _ => (NotificationKind::VersionRejected, "rejected"),
+    };
+    notify::notify(
+        state,
+        actor_id,
+        notify::authors(&thesis)
+            .into_iter()
+            .chain(version.uploader_id),
+        kind,
+        version._id,
+        format!("\"{}\" has been {}.", thesis.title, verdict),
+    )
+    .await
+}
+
 #[debug_handler]
 async fn adjudge(
     auth_info: AuthInfo,
     State(state): State<AppState>,
     Path((id, judgement)): Path<(ObjectId, bool)>,
 ) -> Result<Json<Version>, AppError> {
-    if !auth_info.permitted(Permission::Publishing) {
+    let version = find_version_by_id(&state, id).await?;
+    let thesis = super::thesis::find_thesis_by_id(&state, version.thesis_id).await?;
+    if !auth_info
+        .permitted_on(&state, Permission::Publishing, &thesis)
+        .await?
+    {
         return Err(AppError::Forbidden("you are not a editor".to_string()));
     }
-    let version = find_version_by_id(&state, id).await?;
     match version.state {
         VersionState::Uploaded | VersionState::Reviewing => {}
         _ => {
@@ -309,12 +388,23 @@ async fn adjudge(
             )));
         }
     }
+    let before = to_bson(&version)?;
     let res = if judgement {
-        version.pass(state.mongo_db).await
+        version.pass(state.mongo_db.clone()).await
     } else {
-        version.reject(state.mongo_db).await
+        version.reject(state.mongo_db.clone()).await
     }?
     .ok_or(anyhow::anyhow!("Cannot get updated id!"))?;
+    audit::record(
+        &state,
+        auth_info.id()?,
+        AuditAction::VersionAdjudged,
+        id,
+        Some(before),
+        Some(to_bson(&res)?),
+    )
+    .await?;
+    notify_verdict(&state, auth_info.id()?, &res).await?;
 
     Ok(Json(res))
 }
@@ -345,11 +435,89 @@ async fn comment(
     body.target_id = id;
     body.target_type = CommentTargetType::Version;
     let res = super::comment::insert_comment(&state, body).await?;
+    let thesis = super::thesis::find_thesis_by_id(&state, version.thesis_id).await?;
+    notify::notify(
+        &state,
+        auth_info.id()?,
+        notify::authors(&thesis),
+        NotificationKind::CommentPosted,
+        res,
+        format!("\"{}\" has a new comment.", thesis.title),
+    )
+    .await?;
     Ok((StatusCode::CREATED, Json(res)))
 }
 
+#[derive(Deserialize)]
+struct QueueQuery {
+    /// Every magazine when omitted, which only global editors may ask for.
+    #[serde(default)]
+    magazine_id: Option<ObjectId>,
+}
+
+/// Versions still waiting for an editor's decision, oldest first.
+#[debug_handler]
+async fn queue(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Query(query): Query<AppQuery>,
+    Query(queue): Query<QueueQuery>,
+) -> Result<([(HeaderName, HeaderValue); 4], Json<Vec<Version>>), AppError> {
+    let scope = queue.magazine_id.map_or(Scope::Global, Scope::Magazine);
+    if !auth_info.permitted(Permission::Publishing, scope) {
+        return Err(AppError::Forbidden("you are not a editor".to_string()));
+    }
+    let mut filter = doc! {
+        field!(state in Version): {
+            "$in": [to_bson(&VersionState::Uploaded)?, to_bson(&VersionState::Reviewing)?]
+        }
+    };
+    if let Some(magazine_id) = queue.magazine_id {
+        let thesis_ids = state
+            .mongo_db
+            .repository::<Thesis>()
+            .distinct(
+                "_id",
+                doc! {
+                    field!(magazine_id in Thesis): magazine_id
+                },
+                None,
+            )
+            .await?;
+        filter.insert(
+            field!(thesis_id in Version),
+            doc! {
+                "$in": thesis_ids
+            },
+        );
+    }
+    let count = state
+        .mongo_db
+        .repository::<Version>()
+        .count_documents(filter.clone(), None)
+        .await?;
+    let res = state
+        .mongo_db
+        .repository::<Version>()
+        .find(
+            filter,
+            MongoFindOptions::builder()
+                .sort(doc! {
+                    field!(uploaded_at in Version): 1
+                })
+                .skip(query.offset)
+                .limit(query.limit)
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok((query.pagenate(count), Json(res)))
+}
+
 pub(super) fn new() -> Router<AppState> {
     Router::new()
+        .route("/queue", routing::get(queue))
         .route("/:id", routing::get(get))
         //.route("/:id/file", routing::get(download))
         .route("/:id/edit", routing::patch(edit))
@@ -1,5 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.3
 
+use super::sea_orm_active_enums::HashAlgorithm;
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -10,13 +11,61 @@ pub struct Model {
     pub salt: Uuid,
     #[sea_orm(column_type = "Binary(BlobSize::Blob(None))")]
     pub password_hash: Vec<u8>,
-    pub is_administrator: bool,
-    pub is_editor: bool,
+    pub hash_algorithm: HashAlgorithm,
+    pub hash_cost: i16,
+    pub is_verified: bool,
+    pub is_active: bool,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub totp_secret: Option<Vec<u8>>,
+    pub totp_enabled: bool,
+    pub totp_step: Option<i64>,
+    pub sessions_revoked_at: Option<DateTime>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::account_token::Entity")]
+    AccountToken,
+    #[sea_orm(has_many = "super::api_token::Entity")]
+    ApiToken,
+    #[sea_orm(has_many = "super::oidc_identity::Entity")]
+    OidcIdentity,
+    #[sea_orm(has_many = "super::recovery_code::Entity")]
+    RecoveryCode,
+    #[sea_orm(has_many = "super::role_grant::Entity")]
+    RoleGrant,
+}
+
+impl Related<super::account_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AccountToken.def()
+    }
+}
+
+impl Related<super::api_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiToken.def()
+    }
+}
+
+impl Related<super::oidc_identity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OidcIdentity.def()
+    }
+}
+
+impl Related<super::recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecoveryCode.def()
+    }
+}
+
+impl Related<super::role_grant::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RoleGrant.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
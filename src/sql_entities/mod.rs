@@ -3,3 +3,9 @@
 pub mod prelude;
 
 pub mod account;
+pub mod account_token;
+pub mod api_token;
+pub mod oidc_identity;
+pub mod recovery_code;
+pub mod role_grant;
+pub mod sea_orm_active_enums;
@@ -1,3 +1,8 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.3
 
 pub use super::account::Entity as Account;
+pub use super::account_token::Entity as AccountToken;
+pub use super::api_token::Entity as ApiToken;
+pub use super::oidc_identity::Entity as OidcIdentity;
+pub use super::recovery_code::Entity as RecoveryCode;
+pub use super::role_grant::Entity as RoleGrant;
@@ -0,0 +1,33 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub(super) struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE TABLE IF NOT EXISTS account (
+                    email varchar PRIMARY KEY,
+                    salt uuid NOT NULL,
+                    password_hash bytea NOT NULL,
+                    is_administrator boolean NOT NULL,
+                    is_editor boolean NOT NULL,
+                    created_at timestamp NOT NULL,
+                    updated_at timestamp NOT NULL
+                );",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP TABLE account;")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub(super) struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared(&super::create_type("token_purpose", &["verify"]))
+            .await?;
+        // Accounts that could log in before confirmation existed stay able to.
+        db.execute_unprepared(
+            "ALTER TABLE account ADD COLUMN IF NOT EXISTS is_verified boolean NOT NULL DEFAULT true;
+            ALTER TABLE account ALTER COLUMN is_verified DROP DEFAULT;
+            CREATE TABLE IF NOT EXISTS account_token (
+                token_hash bytea PRIMARY KEY,
+                email varchar NOT NULL REFERENCES account (email) ON UPDATE CASCADE ON DELETE CASCADE,
+                purpose token_purpose NOT NULL,
+                created_at timestamp NOT NULL,
+                expires_at timestamp NOT NULL
+            );",
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "DROP TABLE account_token;
+                ALTER TABLE account DROP COLUMN is_verified;
+                DROP TYPE token_purpose;",
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub(super) struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TYPE token_purpose ADD VALUE IF NOT EXISTS 'reset';
+                ALTER TABLE account ADD COLUMN IF NOT EXISTS sessions_revoked_at timestamp;",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Postgres cannot drop a value from an enum type, so `reset` stays.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "DELETE FROM account_token WHERE purpose = 'reset';
+                ALTER TABLE account DROP COLUMN sessions_revoked_at;",
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub(super) struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared(&super::create_type("hash_algorithm", &["bcrypt"]))
+            .await?;
+        // Existing hashes were made with bcrypt at the configured cost, which migrations
+        // cannot see; `backfill_hash_costs` fills it in before anything is served.
+        db.execute_unprepared(
+            "ALTER TABLE account ADD COLUMN IF NOT EXISTS hash_algorithm hash_algorithm NOT NULL DEFAULT 'bcrypt';
+            ALTER TABLE account ADD COLUMN IF NOT EXISTS hash_cost smallint;
+            ALTER TABLE account ALTER COLUMN hash_algorithm DROP DEFAULT;",
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE account DROP COLUMN hash_cost;
+                ALTER TABLE account DROP COLUMN hash_algorithm;
+                DROP TYPE hash_algorithm;",
+            )
+            .await?;
+        Ok(())
+    }
+}
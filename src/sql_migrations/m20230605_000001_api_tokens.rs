@@ -0,0 +1,35 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub(super) struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE TABLE IF NOT EXISTS api_token (
+                    id uuid PRIMARY KEY,
+                    email varchar NOT NULL REFERENCES account (email) ON UPDATE CASCADE ON DELETE CASCADE,
+                    name varchar NOT NULL,
+                    token_hash bytea NOT NULL UNIQUE,
+                    scopes jsonb NOT NULL,
+                    created_at timestamp NOT NULL,
+                    expires_at timestamp,
+                    last_used_at timestamp,
+                    revoked_at timestamp
+                );",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP TABLE api_token;")
+            .await?;
+        Ok(())
+    }
+}
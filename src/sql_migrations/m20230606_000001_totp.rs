@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub(super) struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE account ADD COLUMN IF NOT EXISTS totp_secret bytea;
+                ALTER TABLE account ADD COLUMN IF NOT EXISTS totp_enabled boolean NOT NULL DEFAULT false;
+                ALTER TABLE account ADD COLUMN IF NOT EXISTS totp_step bigint;
+                CREATE TABLE IF NOT EXISTS recovery_code (
+                    code_hash bytea PRIMARY KEY,
+                    email varchar NOT NULL REFERENCES account (email) ON UPDATE CASCADE ON DELETE CASCADE,
+                    created_at timestamp NOT NULL
+                );",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "DROP TABLE recovery_code;
+                ALTER TABLE account DROP COLUMN totp_step;
+                ALTER TABLE account DROP COLUMN totp_enabled;
+                ALTER TABLE account DROP COLUMN totp_secret;",
+            )
+            .await?;
+        Ok(())
+    }
+}
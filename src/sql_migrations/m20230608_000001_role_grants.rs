@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub(super) struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared(&super::create_type(
+            "role",
+            &["administrator", "auditor", "editor", "reviewer"],
+        ))
+        .await?;
+        db.execute_unprepared(&super::create_type(
+            "scope_kind",
+            &["category", "global", "magazine"],
+        ))
+        .await?;
+        db.execute_unprepared(
+            "CREATE TABLE IF NOT EXISTS role_grant (
+                id uuid PRIMARY KEY,
+                email varchar NOT NULL REFERENCES account (email) ON UPDATE CASCADE ON DELETE CASCADE,
+                role role NOT NULL,
+                scope_kind scope_kind NOT NULL,
+                scope_id varchar,
+                granted_at timestamp NOT NULL
+            );
+            CREATE UNIQUE INDEX IF NOT EXISTS role_grant_unique
+                ON role_grant (email, role, scope_kind, COALESCE(scope_id, ''));",
+        )
+        .await?;
+        // The two flags become global grants of the roles they stood for before they go.
+        db.execute_unprepared(
+            "DO $$ BEGIN
+                IF EXISTS (
+                    SELECT FROM information_schema.columns
+                    WHERE table_name = 'account' AND column_name = 'is_administrator'
+                ) THEN
+                    INSERT INTO role_grant (id, email, role, scope_kind, scope_id, granted_at)
+                    SELECT gen_random_uuid(), email, 'administrator', 'global', NULL, now() AT TIME ZONE 'utc'
+                    FROM account WHERE is_administrator;
+                    INSERT INTO role_grant (id, email, role, scope_kind, scope_id, granted_at)
+                    SELECT gen_random_uuid(), email, 'editor', 'global', NULL, now() AT TIME ZONE 'utc'
+                    FROM account WHERE is_editor;
+                    ALTER TABLE account DROP COLUMN is_administrator;
+                    ALTER TABLE account DROP COLUMN is_editor;
+                END IF;
+            END $$;",
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE account ADD COLUMN is_administrator boolean NOT NULL DEFAULT false;
+                ALTER TABLE account ADD COLUMN is_editor boolean NOT NULL DEFAULT false;
+                UPDATE account SET is_administrator = true WHERE email IN (
+                    SELECT email FROM role_grant WHERE role = 'administrator' AND scope_kind = 'global'
+                );
+                UPDATE account SET is_editor = true WHERE email IN (
+                    SELECT email FROM role_grant WHERE role = 'editor' AND scope_kind = 'global'
+                );
+                DROP TABLE role_grant;
+                DROP TYPE scope_kind;
+                DROP TYPE role;",
+            )
+            .await?;
+        Ok(())
+    }
+}
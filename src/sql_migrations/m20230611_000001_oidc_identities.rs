@@ -0,0 +1,31 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub(super) struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE TABLE IF NOT EXISTS oidc_identity (
+                    issuer varchar NOT NULL,
+                    subject varchar NOT NULL,
+                    email varchar NOT NULL REFERENCES account (email) ON UPDATE CASCADE ON DELETE CASCADE,
+                    linked_at timestamp NOT NULL,
+                    PRIMARY KEY (issuer, subject)
+                );",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP TABLE oidc_identity;")
+            .await?;
+        Ok(())
+    }
+}
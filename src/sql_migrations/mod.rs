@@ -0,0 +1,62 @@
+use sea_orm::{ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use sea_orm_migration::prelude::*;
+
+use crate::sql_entities::account;
+use crate::sql_entities::prelude::Account;
+
+mod m20230601_000001_baseline;
+mod m20230602_000001_verify_accounts;
+mod m20230603_000001_reset_passwords;
+mod m20230604_000001_hash_algorithms;
+mod m20230605_000001_api_tokens;
+mod m20230606_000001_totp;
+mod m20230608_000001_role_grants;
+mod m20230611_000001_oidc_identities;
+mod m20230613_000001_deactivate_accounts;
+
+pub(crate) struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20230601_000001_baseline::Migration),
+            Box::new(m20230602_000001_verify_accounts::Migration),
+            Box::new(m20230603_000001_reset_passwords::Migration),
+            Box::new(m20230604_000001_hash_algorithms::Migration),
+            Box::new(m20230605_000001_api_tokens::Migration),
+            Box::new(m20230606_000001_totp::Migration),
+            Box::new(m20230608_000001_role_grants::Migration),
+            Box::new(m20230611_000001_oidc_identities::Migration),
+            Box::new(m20230613_000001_deactivate_accounts::Migration),
+        ]
+    }
+}
+
+/// Hashes stored before their cost was recorded were made at the configured one.
+pub(crate) async fn backfill_hash_costs(
+    db: &DatabaseConnection,
+    hash_cost: u8,
+) -> Result<(), DbErr> {
+    Account::update_many()
+        .col_expr(account::Column::HashCost, Expr::value(i16::from(hash_cost)))
+        .filter(account::Column::HashCost.is_null())
+        .exec(db)
+        .await?;
+    db.execute_unprepared("ALTER TABLE account ALTER COLUMN hash_cost SET NOT NULL;")
+        .await?;
+    Ok(())
+}
+
+/// Postgres has no `CREATE TYPE IF NOT EXISTS`, and schemas that were set up by hand already have the types.
+fn create_type(name: &str, values: &[&str]) -> String {
+    let values = values
+        .iter()
+        .map(|value| format!("'{}'", value))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "DO $$ BEGIN CREATE TYPE {} AS ENUM ({}); EXCEPTION WHEN duplicate_object THEN NULL; END $$;",
+        name, values
+    )
+}
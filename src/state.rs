@@ -1,10 +1,33 @@
 use std::sync::Arc;
 
+use crate::mongo_entities::notification::Notification;
+
 #[derive(Clone)]
 pub(crate) struct AppState {
     pub(crate) sql_db: sea_orm::DatabaseConnection,
     pub(crate) mongo_db: mongodm::prelude::MongoDatabase,
+    pub(crate) clt_addr: Arc<String>,
+    pub(crate) srv_url: Arc<String>,
     pub(crate) hash_cost: u8,
+    pub(crate) verification_ttl: chrono::Duration,
+    pub(crate) reset_ttl: chrono::Duration,
+    pub(crate) login_failure_limit: i32,
+    pub(crate) ip_failure_limit: i32,
+    pub(crate) lockout_max: chrono::Duration,
+    pub(crate) trusted_proxies: Arc<Vec<std::net::IpAddr>>,
+    pub(crate) forwarded_header: axum::http::HeaderName,
+    pub(crate) totp_issuer: Arc<String>,
+    pub(crate) totp_required: bool,
+    pub(crate) oidc_issuer: Option<Arc<String>>,
+    pub(crate) oidc_client_id: Arc<String>,
+    pub(crate) oidc_client_secret: Option<Arc<String>>,
+    pub(crate) oidc_redirect_url: Arc<String>,
+    pub(crate) avatar_max_size: usize,
+    pub(crate) crossref_depositor: Arc<lettre::message::Mailbox>,
+    pub(crate) oai_base_url: Arc<url::Url>,
+    pub(crate) oai_repository_name: Arc<String>,
+    /// Notifications of everyone as stored by any replica, for whoever is streaming them live.
+    pub(crate) events: tokio::sync::broadcast::Sender<Arc<Notification>>,
     pub(crate) sender: Arc<lettre::message::Mailbox>,
     pub(crate) smtp: lettre::AsyncSmtpTransport<lettre::Tokio1Executor>,
 }